@@ -0,0 +1,151 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::argv;
+use crate::argv::ast::Segment;
+use crate::argv::error::ArgumentError;
+
+/// Split `source`'s leading whitespace and first word off, returning the
+/// word's text and the remainder of the line, or `None` if the word is not
+/// a single unquoted, unescaped [Segment::Literal] -- i.e. it was written
+/// `\name` or `'name'`, which must suppress alias lookup.
+fn split_first_word(source: &str) -> Result<Option<(String, String)>, ArgumentError> {
+    let trimmed = source.trim_start();
+
+    let first_word = match argv::ast::parse_line(trimmed)?.into_iter().next() {
+        Some(word) => word,
+        None => return Ok(None),
+    };
+
+    match first_word.segments.as_slice() {
+        [Segment::Literal(name)] => {
+            let rest = trimmed[name.len()..].to_string();
+
+            Ok(Some((name.clone(), rest)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Expand a leading alias on `source` against `aliases`, re-scanning the
+/// result in case the alias body itself begins with another alias, the way
+/// bash does. An alias name is tracked once it has triggered a substitution
+/// in this pass, so a self-referential or mutually recursive alias (`ls`
+/// expanding to `ls -l`, or `a` expanding to `b` expanding back to `a`)
+/// stops instead of looping forever -- bash's own rule of not re-expanding a
+/// name that already expanded once in the current pass.
+///
+/// Quoting suppresses alias lookup: `\ls` or `'ls'` are left untouched
+/// because they no longer parse as a single unquoted [Segment::Literal].
+pub fn expand(source: &str, aliases: &BTreeMap<String, String>) -> Result<String, ArgumentError> {
+    let mut current = source.to_string();
+    let mut expanded = HashSet::new();
+
+    while let Some((name, rest)) = split_first_word(&current)? {
+        if expanded.contains(&name) {
+            break;
+        }
+
+        let body = match aliases.get(&name) {
+            Some(body) => body,
+            None => break,
+        };
+
+        expanded.insert(name);
+        current = format!("{}{}", body, rest);
+    }
+
+    Ok(current)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use crate::argv::alias;
+
+    fn aliases(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, body)| (name.to_string(), body.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_no_aliases() {
+        let expected = Ok("ls -a".to_string());
+        let actual = alias::expand("ls -a", &BTreeMap::new());
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_simple_expansion() {
+        let expected = Ok("ls -l -a".to_string());
+        let actual = alias::expand("ls -a", &aliases(&[("ls", "ls -l")]));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_chained_expansion() {
+        let expected = Ok("ls -l".to_string());
+        let actual = alias::expand("ll", &aliases(&[("ll", "la"), ("la", "ls -l")]));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_only_leading_word_is_expanded() {
+        let expected = Ok("echo ll".to_string());
+        let actual = alias::expand("echo ll", &aliases(&[("ll", "ls -l")]));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_self_referential_alias_does_not_loop() {
+        let expected = Ok("ls -a".to_string());
+        let actual = alias::expand("ls", &aliases(&[("ls", "ls -a")]));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_mutually_recursive_aliases_do_not_loop() {
+        let expected = Ok("a".to_string());
+        let actual = alias::expand("a", &aliases(&[("a", "b"), ("b", "a")]));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_backslash_escape_suppresses_lookup() {
+        let expected = Ok("\\ls".to_string());
+        let actual = alias::expand("\\ls", &aliases(&[("ls", "ls -l")]));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_single_quote_suppresses_lookup() {
+        let expected = Ok("'ls'".to_string());
+        let actual = alias::expand("'ls'", &aliases(&[("ls", "ls -l")]));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_empty_line() {
+        let expected = Ok("".to_string());
+        let actual = alias::expand("", &aliases(&[("ls", "ls -l")]));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_unterminated_quote_surfaces_as_argument_error() {
+        let actual = alias::expand("'ls", &BTreeMap::new());
+
+        assert!(actual.is_err());
+    }
+}
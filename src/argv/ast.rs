@@ -0,0 +1,277 @@
+use pest::error::InputLocation;
+use pest::iterators::Pair;
+
+use crate::argv::error::{ArgumentError, Span};
+
+#[derive(pest_derive::Parser)]
+#[grammar = "argv/grammar.pest"]
+struct LineParser;
+
+/// A single piece of a [Word]. Quote removal and the tilde-only-at-word-start
+/// rule both fall out of which variant a piece of text ended up as, rather
+/// than being tracked separately alongside the raw characters the way the
+/// old char-by-char scanners had to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    /// Plain, unquoted text with no expansion left to do.
+    Literal(String),
+
+    /// The raw (quotes stripped) text of a `'...'` span. Never expanded.
+    SingleQuoted(String),
+
+    /// The segments found inside a `"..."` span. `Var`/`CommandSub` segments
+    /// in here are still expanded; `Literal`/`Escape` are not re-scanned for
+    /// quotes, tildes, or globs.
+    DoubleQuoted(Vec<Segment>),
+
+    /// A `~`, `~+`, `~-`, or `~name` tilde-prefix, holding whatever followed
+    /// the `~` (empty for a bare `~`). Only expanded when it is the first
+    /// segment of its [Word]; elsewhere it is a literal tilde-prefix.
+    Tilde(String),
+
+    /// The raw text of a `$name` or `${...}` expansion, handed to
+    /// [crate::argv::expand::expand_vars] as-is.
+    Var(String),
+
+    /// The raw text of a `$(...)` or `` `...` `` command substitution,
+    /// handed to [crate::argv::expand::expand_vars] as-is.
+    CommandSub(String),
+
+    /// An unquoted `\c` escape.
+    Escape(char),
+}
+
+/// A single whitespace-delimited word, made up of the [Segment]s the grammar
+/// recognized in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    pub segments: Vec<Segment>,
+}
+
+/// Parse `source` into the words the grammar recognizes. A trailing `#`
+/// comment (only recognized at the start of a word, matching the comment
+/// handling in [crate::argv::split]) is dropped.
+pub fn parse_line(source: &str) -> Result<Vec<Word>, ArgumentError> {
+    let mut pairs = LineParser::parse(Rule::line, source).map_err(|err| {
+        let span = match &err.location {
+            InputLocation::Pos(pos) => Span::at(*pos),
+            InputLocation::Span((start, end)) => Span::new(*start, *end),
+        };
+
+        ArgumentError::Custom(err.to_string(), span)
+    })?;
+
+    let line = pairs.next().unwrap();
+
+    let mut words = vec![];
+
+    for pair in line.into_inner() {
+        match pair.as_rule() {
+            Rule::word => words.push(Word {
+                segments: parse_segments(pair)?,
+            }),
+            Rule::comment | Rule::EOI => {}
+            _ => unreachable!("line may only contain words, a comment, or EOI"),
+        }
+    }
+
+    Ok(words)
+}
+
+fn parse_segments(word: Pair<Rule>) -> Result<Vec<Segment>, ArgumentError> {
+    word.into_inner().map(parse_segment).collect()
+}
+
+fn parse_segment(pair: Pair<Rule>) -> Result<Segment, ArgumentError> {
+    match pair.as_rule() {
+        Rule::single_quoted => {
+            let inner = pair.into_inner().next().unwrap();
+
+            Ok(Segment::SingleQuoted(inner.as_str().to_string()))
+        }
+        Rule::double_quoted => Ok(Segment::DoubleQuoted(parse_segments(pair)?)),
+        Rule::command_sub => Ok(Segment::CommandSub(pair.as_str().to_string())),
+        Rule::variable => Ok(Segment::Var(pair.as_str().to_string())),
+        Rule::tilde => Ok(Segment::Tilde(pair.as_str()[1..].to_string())),
+        Rule::escape => Ok(Segment::Escape(pair.as_str().chars().nth(1).unwrap())),
+        Rule::double_escape => Ok(Segment::Escape(pair.as_str().chars().nth(1).unwrap())),
+        Rule::literal | Rule::double_literal => Ok(Segment::Literal(pair.as_str().to_string())),
+        rule => unreachable!("unexpected segment rule {:?}", rule),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::argv::ast::{self, Segment, Word};
+
+    #[test]
+    fn test_empty_line() {
+        let expected: Vec<Word> = vec![];
+        let actual = ast::parse_line("").unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_single_literal_word() {
+        let expected = vec![Word {
+            segments: vec![Segment::Literal("cmd".to_string())],
+        }];
+        let actual = ast::parse_line("cmd").unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_multiple_words() {
+        let expected = vec![
+            Word {
+                segments: vec![Segment::Literal("a".to_string())],
+            },
+            Word {
+                segments: vec![Segment::Literal("b".to_string())],
+            },
+        ];
+        let actual = ast::parse_line("a  b").unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_single_quoted_segment() {
+        let expected = vec![Word {
+            segments: vec![Segment::SingleQuoted("a b".to_string())],
+        }];
+        let actual = ast::parse_line("'a b'").unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_mid_word_tilde_is_just_a_segment() {
+        let expected = vec![Word {
+            segments: vec![
+                Segment::Literal("a".to_string()),
+                Segment::Tilde("b".to_string()),
+            ],
+        }];
+        let actual = ast::parse_line("a~b").unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_leading_tilde_segment() {
+        let expected = vec![Word {
+            segments: vec![Segment::Tilde("b".to_string())],
+        }];
+        let actual = ast::parse_line("~b").unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_tilde_stops_at_slash() {
+        let expected = vec![Word {
+            segments: vec![
+                Segment::Tilde("user".to_string()),
+                Segment::Literal("/a".to_string()),
+            ],
+        }];
+        let actual = ast::parse_line("~user/a").unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_tilde_plus_and_minus() {
+        let expected = vec![
+            Word {
+                segments: vec![Segment::Tilde("+".to_string())],
+            },
+            Word {
+                segments: vec![Segment::Tilde("-".to_string())],
+            },
+        ];
+        let actual = ast::parse_line("~+ ~-").unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_variable_segment() {
+        let expected = vec![Word {
+            segments: vec![Segment::Var("$HOME".to_string())],
+        }];
+        let actual = ast::parse_line("$HOME").unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_brace_variable_segment() {
+        let expected = vec![Word {
+            segments: vec![Segment::Var("${HOME:-/}".to_string())],
+        }];
+        let actual = ast::parse_line("${HOME:-/}").unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_command_sub_segment() {
+        let expected = vec![Word {
+            segments: vec![Segment::CommandSub("$(echo hi)".to_string())],
+        }];
+        let actual = ast::parse_line("$(echo hi)").unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_nested_command_sub_segment() {
+        let expected = vec![Word {
+            segments: vec![Segment::CommandSub("$(echo $(echo a))".to_string())],
+        }];
+        let actual = ast::parse_line("$(echo $(echo a))").unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_double_quoted_segment_expands_vars() {
+        let expected = vec![Word {
+            segments: vec![Segment::DoubleQuoted(vec![
+                Segment::Literal("a ".to_string()),
+                Segment::Var("$B".to_string()),
+            ])],
+        }];
+        let actual = ast::parse_line("\"a $B\"").unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_escape_segment() {
+        let expected = vec![Word {
+            segments: vec![
+                Segment::Literal("a".to_string()),
+                Segment::Escape(' '),
+                Segment::Literal("b".to_string()),
+            ],
+        }];
+        let actual = ast::parse_line("a\\ b").unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_trailing_comment_is_dropped() {
+        let expected = vec![Word {
+            segments: vec![Segment::Literal("cmd".to_string())],
+        }];
+        let actual = ast::parse_line("cmd # a comment").unwrap();
+
+        assert_eq!(expected, actual);
+    }
+}
@@ -1,27 +1,146 @@
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
 
+/// A byte-offset range into the original source line an [ArgumentError]
+/// spans, used to underline the offending text rather than a single column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// A zero-width span at a single byte offset, for errors with no
+    /// natural end, like running out of input mid-escape.
+    pub fn at(position: usize) -> Span {
+        Span {
+            start: position,
+            end: position,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum ArgumentError {
     /// Found end of line but more content was expected or needed for proper
-    /// argument parsing.
-    UnexpectedEndOfLine,
+    /// argument parsing. Carries the span where the input ran out.
+    UnexpectedEndOfLine(Span),
+
+    /// A sequence was started but not properlly ended. Carries the
+    /// character that opened the sequence and the span from its opening
+    /// through the point parsing gave up looking for the close.
+    UnterminatedSequence(char, Span),
+
+    /// An invalid escape sequence was found. Carries the span of the
+    /// escaped character.
+    InvalidEscape(char, Span),
+
+    /// A user-supplied message, e.g. from `${VAR:?message}`. Carries the
+    /// span of the expansion that raised it.
+    Custom(String, Span),
+}
+
+impl ArgumentError {
+    /// The span into the source this error occurred at.
+    pub fn span(&self) -> Span {
+        match self {
+            ArgumentError::UnexpectedEndOfLine(span) => *span,
+            ArgumentError::UnterminatedSequence(_, span) => *span,
+            ArgumentError::InvalidEscape(_, span) => *span,
+            ArgumentError::Custom(_, span) => *span,
+        }
+    }
+
+    /// The byte offset this error's span starts at, for callers that only
+    /// want a single column.
+    pub fn position(&self) -> usize {
+        self.span().start
+    }
 
-    /// A sequence was started but not properlly ended.
-    UnterminatedSequence(char),
+    /// Reproduce the line of `source` containing this error's span with
+    /// `^` carets underlining the offending text, so a REPL can show the
+    /// user precisely what broke.
+    pub fn annotate(&self, source: &str) -> String {
+        let span = self.span();
+        let start = span.start.min(source.len());
+        let end = span.end.max(start).min(source.len());
 
-    /// An invalid escape sequence was found.
-    InvalidEscape(char),
+        let line_start = source[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = source[start..]
+            .find('\n')
+            .map(|i| start + i)
+            .unwrap_or_else(|| source.len());
+
+        let line = &source[line_start..line_end];
+        let column = start - line_start;
+        let width = end.min(line_end).saturating_sub(start).max(1);
+
+        format!("{}\n{}{}", line, " ".repeat(column), "^".repeat(width))
+    }
 }
 
 impl Display for ArgumentError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let position = self.position();
+
         match self {
-            ArgumentError::UnexpectedEndOfLine => write!(f, "received unexpected end of line"),
-            ArgumentError::UnterminatedSequence(c) => write!(f, "received unterminated '{}' sequence", c),
-            ArgumentError::InvalidEscape(c) => write!(f, "received invalid escpace character'{}'", c),
+            ArgumentError::UnexpectedEndOfLine(_) => {
+                write!(f, "received unexpected end of line at byte {}", position)
+            }
+            ArgumentError::UnterminatedSequence(c, _) => write!(
+                f,
+                "received unterminated '{}' sequence at byte {}",
+                c, position
+            ),
+            ArgumentError::InvalidEscape(c, _) => write!(
+                f,
+                "received invalid escpace character'{}' at byte {}",
+                c, position
+            ),
+            ArgumentError::Custom(message, _) => {
+                write!(f, "{} at byte {}", message, position)
+            }
         }
     }
 }
 
-impl Error for ArgumentError { }
\ No newline at end of file
+impl Error for ArgumentError {}
+
+#[cfg(test)]
+mod test {
+    use crate::argv::error::{ArgumentError, Span};
+
+    #[test]
+    fn test_annotate_single_line() {
+        let err = ArgumentError::UnterminatedSequence('\'', Span::new(6, 10));
+
+        let expected = "cmd a 'b c\n      ^^^^";
+        let actual = err.annotate("cmd a 'b c");
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_annotate_multi_line() {
+        let err = ArgumentError::UnterminatedSequence('"', Span::new(6, 8));
+
+        let expected = "cmd \"b\n    ^^";
+        let actual = err.annotate("a\ncmd \"b");
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_annotate_zero_width_span() {
+        let err = ArgumentError::UnexpectedEndOfLine(Span::at(6));
+
+        let expected = "cmd a\\\n      ^";
+        let actual = err.annotate("cmd a\\");
+
+        assert_eq!(expected, actual);
+    }
+}
@@ -1,65 +1,324 @@
 use std::env;
+use std::process::{Command, Stdio};
 
 use crate::argv;
-use crate::argv::error::ArgumentError;
-
-/// Replace all non-quoted  and non-escaped tildes with the user's home
-/// directory.The `provider` should be a simple method that returns the user's
-/// home directory as a string, or None if the home directory could not be
-/// determined. If the user's home directory could not be determined, the tilde
-/// is not expanded and left as-is.
-fn expand_tilde<F>(source: &str, provider: F) -> Result<String, ArgumentError>
-where
-    F: FnOnce() -> Option<String>,
-{
-    if !source.contains('~') {
-        Ok(source.to_string())
-    } else {
-        let mut expanded = String::new();
-        let mut chars = source.chars().enumerate();
+use crate::argv::error::{ArgumentError, Span};
+use crate::error::WrashError;
+
+/// Find the `}` matching the `{` opened just before `source[start]`, honoring nested
+/// `{}` groups (so a default value may itself contain braces), `$(...)`
+/// command substitutions, and single-quoted regions, which are skipped over
+/// verbatim. Returns the byte offset of the matching `}`, or `None` if the
+/// brace is never closed.
+fn find_matching_brace(source: &str, start: usize) -> Option<usize> {
+    let mut depth = 1;
+    let mut chars = source[start..].char_indices().peekable();
 
-        let home = match provider() {
-            Some(home) => home,
-            None => "~".to_string(),
-        };
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
 
-        while let Some((start, c)) = chars.next() {
-            match c {
-                '\'' | '"' => {
-                    if let Some((end, _)) = argv::find_with_previous(
-                        &mut chars,
-                        |o| {
-                            if let Some((_, c)) = o {
-                                *c != '\\'
-                            } else {
-                                true
-                            }
-                        },
-                        |(_, current)| *current == c,
-                    ) {
-                        expanded.push_str(&source[start..end + 1]);
-                    } else {
-                        return Err(ArgumentError::UnterminatedSequence(c));
+                if depth == 0 {
+                    return Some(start + i);
+                }
+            }
+            '\'' => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\'' {
+                        break;
                     }
                 }
-                '\\' => match chars.next() {
-                    Some((_, '~')) => expanded.push('~'),
-                    Some((_, c)) => {
-                        expanded.push('\\');
-                        expanded.push(c);
+            }
+            '$' if chars.peek().map(|(_, c)| *c) == Some('(') => {
+                chars.next();
+
+                let mut paren_depth = 1;
+
+                while paren_depth > 0 {
+                    match chars.next() {
+                        Some((_, '(')) => paren_depth += 1,
+                        Some((_, ')')) => paren_depth -= 1,
+                        Some(_) => {}
+                        None => break,
                     }
-                    None => return Err(ArgumentError::UnterminatedSequence('\\')),
-                },
-                '~' => expanded.push_str(home.as_str()),
-                _ => expanded.push(c),
+                }
             }
+            _ => {}
         }
+    }
 
-        Ok(expanded)
+    None
+}
+
+/// Match `candidate` against `pattern` using the same glob syntax as
+/// filename expansion (see [glob_paths]).
+fn glob_matches(candidate: &str, pattern: &str) -> bool {
+    glob::Pattern::new(pattern)
+        .map(|p| p.matches(candidate))
+        .unwrap_or(false)
+}
+
+/// Remove the shortest prefix of `value` matching the glob `pattern`, for
+/// `${VAR#pattern}`.
+fn remove_shortest_prefix(value: &str, pattern: &str) -> String {
+    for end in 0..=value.len() {
+        if !value.is_char_boundary(end) {
+            continue;
+        }
+
+        if glob_matches(&value[..end], pattern) {
+            return value[end..].to_string();
+        }
+    }
+
+    value.to_string()
+}
+
+/// Remove the shortest suffix of `value` matching the glob `pattern`, for
+/// `${VAR%pattern}`.
+fn remove_shortest_suffix(value: &str, pattern: &str) -> String {
+    for start in (0..=value.len()).rev() {
+        if !value.is_char_boundary(start) {
+            continue;
+        }
+
+        if glob_matches(&value[start..], pattern) {
+            return value[..start].to_string();
+        }
+    }
+
+    value.to_string()
+}
+
+/// Apply `${VAR:offset:length}` substring extraction; `spec` is the text
+/// following the initial `:` (e.g. `"2:3"` or `"-2"`). A negative offset
+/// counts from the end of `value` and both offset and length are clamped to
+/// `value`'s bounds.
+fn substring(value: &str, spec: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    let len = chars.len() as isize;
+
+    let (offset_str, length_str) = match spec.find(':') {
+        Some(idx) => (&spec[..idx], Some(&spec[idx + 1..])),
+        None => (spec, None),
+    };
+
+    let offset: isize = offset_str.trim().parse().unwrap_or(0);
+    let start = if offset < 0 {
+        (len + offset).max(0)
+    } else {
+        offset.min(len)
+    };
+
+    let end = match length_str {
+        Some(length_str) => {
+            let length: isize = length_str.trim().parse().unwrap_or(0);
+            (start + length.max(0)).min(len)
+        }
+        None => len,
+    };
+
+    if end <= start {
+        return String::new();
     }
+
+    chars[start as usize..end as usize].iter().collect()
+}
+
+/// Expand the `${...}` parameter expansion starting at `$` position `i` in
+/// `source`, applying whichever of the POSIX/bash operators is present:
+/// `${#VAR}` (length), `${VAR:-word}`/`${VAR:=word}`/`${VAR:?word}`/`${VAR:+word}`
+/// (default/assign/error/alternate), `${VAR:offset:length}` (substring), and
+/// `${VAR#pat}`/`${VAR%pat}` (shortest prefix/suffix removal). Returns the
+/// expanded value and the byte offset immediately after the closing `}`.
+fn expand_brace_param(source: &str, i: usize) -> Result<(String, usize), ArgumentError> {
+    let end = find_matching_brace(source, i + 2)
+        .ok_or_else(|| ArgumentError::UnterminatedSequence('{', Span::new(i, source.len())))?;
+    let content = &source[i + 2..end];
+    let next = end + 1;
+
+    if let Some(name) = content.strip_prefix('#') {
+        let value = env::var(name).unwrap_or_default();
+
+        return Ok((value.chars().count().to_string(), next));
+    }
+
+    let name_end = content
+        .find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or_else(|| content.len());
+    let name = &content[..name_end];
+    let op = &content[name_end..];
+    let current = env::var(name).ok();
+
+    let value = if let Some(word) = op.strip_prefix(":-") {
+        match &current {
+            Some(v) if !v.is_empty() => v.clone(),
+            _ => word.to_string(),
+        }
+    } else if let Some(word) = op.strip_prefix(":=") {
+        match &current {
+            Some(v) if !v.is_empty() => v.clone(),
+            _ => {
+                env::set_var(name, word);
+                word.to_string()
+            }
+        }
+    } else if let Some(word) = op.strip_prefix(":?") {
+        match &current {
+            Some(v) if !v.is_empty() => v.clone(),
+            _ => return Err(ArgumentError::Custom(word.to_string(), Span::new(i, next))),
+        }
+    } else if let Some(word) = op.strip_prefix(":+") {
+        match &current {
+            Some(v) if !v.is_empty() => word.to_string(),
+            _ => String::new(),
+        }
+    } else if let Some(spec) = op.strip_prefix(':') {
+        substring(&current.unwrap_or_default(), spec)
+    } else if let Some(pattern) = op.strip_prefix('#') {
+        remove_shortest_prefix(&current.unwrap_or_default(), pattern)
+    } else if let Some(pattern) = op.strip_prefix('%') {
+        remove_shortest_suffix(&current.unwrap_or_default(), pattern)
+    } else {
+        current.unwrap_or_default()
+    };
+
+    Ok((value, next))
+}
+
+/// Find the `)` matching the `(` opened just before `source[start]` for a `$(...)`
+/// command substitution, honoring nested `(...)` groups (so a nested
+/// `$( $(...) )` is handled by the same depth counting) and single-quoted
+/// regions, which are skipped over verbatim. Returns the byte offset of the
+/// matching `)`, or `None` if the substitution is never closed.
+fn find_matching_paren(source: &str, start: usize) -> Option<usize> {
+    let mut depth = 1;
+    let mut chars = source[start..].char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return Some(start + i);
+                }
+            }
+            '\'' => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Find the backtick matching the one opened just before `source[start]`, treating
+/// `` \` `` as an escaped, literal backtick rather than the closing
+/// delimiter. Returns the byte offset of the matching backtick, or `None` if
+/// one is never found.
+fn find_matching_backtick(source: &str, start: usize) -> Option<usize> {
+    let mut chars = source[start..].char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '`' => return Some(start + i),
+            '\\' => {
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Run `command_line` as an external command, resolved with
+/// [crate::resolve_command] as `mode normal` dispatch resolves it, and
+/// return its captured stdout with trailing newlines stripped. Builtins,
+/// aliases and the wrapped base command are not consulted. `span` is
+/// used only to annotate any [ArgumentError] raised if the command can't be
+/// started or exits non-zero.
+fn run_command_substitution(command_line: &str, span: Span) -> Result<String, ArgumentError> {
+    let command_line = expand_vars(command_line)?;
+
+    let words = argv::split::split_words(&command_line)
+        .map_err(|err| ArgumentError::Custom(err.to_string(), span))?;
+
+    let (name, args) = match words.split_first() {
+        Some((name, args)) => (name.clone(), args.to_vec()),
+        None => return Ok(String::new()),
+    };
+
+    let command = crate::resolve_command(&name)
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or(name);
+
+    let child = Command::new(&command)
+        .args(&args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(WrashError::from)
+        .map_err(|err| ArgumentError::Custom(err.to_string(), span))?;
+
+    let output = child
+        .wait_with_output()
+        .map_err(WrashError::from)
+        .map_err(|err| ArgumentError::Custom(err.to_string(), span))?;
+
+    if !output.status.success() {
+        let code = output.status.code().unwrap_or(255);
+
+        return Err(ArgumentError::Custom(
+            WrashError::NonZeroExit(code).to_string(),
+            span,
+        ));
+    }
+
+    let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+
+    while text.ends_with('\n') {
+        text.pop();
+    }
+
+    Ok(text)
+}
+
+/// Expand the `$(...)` command substitution opened at `source[i]` (the `$`).
+fn expand_command_substitution(source: &str, i: usize) -> Result<(String, usize), ArgumentError> {
+    let end = find_matching_paren(source, i + 2)
+        .ok_or_else(|| ArgumentError::UnterminatedSequence('(', Span::new(i, source.len())))?;
+
+    let value = run_command_substitution(&source[i + 2..end], Span::new(i, end + 1))?;
+
+    Ok((value, end + 1))
+}
+
+/// Expand the backtick command substitution opened at `source[i]`.
+fn expand_backtick_substitution(source: &str, i: usize) -> Result<(String, usize), ArgumentError> {
+    let end = find_matching_backtick(source, i + 1)
+        .ok_or_else(|| ArgumentError::UnterminatedSequence('`', Span::new(i, source.len())))?;
+
+    // `\`` stays escaped here; expand_vars skips it and split_words resolves
+    // it to a literal backtick
+    let inner = &source[i + 1..end];
+    let value = run_command_substitution(inner, Span::new(i, end + 1))?;
+
+    Ok((value, end + 1))
 }
 
-/// Expand all found parameter expansions, bot in and outside of double quotes.
+/// Expand all found parameter expansions and command substitutions
+/// (`$(...)` and backticks), both in and outside of double quotes, in a
+/// single left-to-right pass. Single-quoted regions are left untouched.
 fn expand_vars(source: &str) -> Result<String, ArgumentError> {
     let mut expanded = String::new();
     let mut chars = source.chars().enumerate().peekable();
@@ -72,31 +331,79 @@ fn expand_vars(source: &str) -> Result<String, ArgumentError> {
                     expanded.push_str(&source[last..i]);
                 }
 
-                let (name, next) = match chars.peek() {
-                    None => return Err(ArgumentError::UnterminatedSequence('$')),
+                let next = match chars.peek() {
+                    None => {
+                        return Err(ArgumentError::UnterminatedSequence(
+                            '$',
+                            Span::new(i, source.len()),
+                        ))
+                    }
                     Some((_, '{')) => {
-                        if let Some(n) = source[i + 1..].find('}') {
-                            (&source[i + 2..i + n + 1], i + n + 2)
-                        } else {
-                            return Err(ArgumentError::UnterminatedSequence('{'));
-                        }
+                        let (value, next) = expand_brace_param(source, i)?;
+
+                        expanded.push_str(&value);
+
+                        next
+                    }
+                    Some((_, '(')) => {
+                        let (value, next) = expand_command_substitution(source, i)?;
+
+                        expanded.push_str(&value);
+
+                        next
                     }
                     Some((_, _)) => {
-                        if let Some(n) =
+                        let (name, next) = if let Some(n) =
                             source[i + 1..].find(|c: char| !c.is_alphanumeric() && c != '_')
                         {
                             (&source[i + 1..i + n + 1], i + n + 1)
                         } else {
                             (&source[i + 1..], source.len())
-                        }
+                        };
+
+                        let value = env::var(name).unwrap_or_else(|_| String::new());
+
+                        expanded.push_str(value.as_str());
+
+                        next
                     }
                 };
-                let value = env::var(name).unwrap_or_else(|_| String::new());
 
-                expanded.push_str(value.as_str());
+                // fast-forward past everything we just consumed by index so
+                // it isn't re-scanned as top-level `$`/`'`/backtick characters
+                while let Some(&(j, _)) = chars.peek() {
+                    if j < next {
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
 
                 last = next;
             }
+            '`' => {
+                if i != 0 {
+                    expanded.push_str(&source[last..i]);
+                }
+
+                let (value, next) = expand_backtick_substitution(source, i)?;
+
+                expanded.push_str(&value);
+
+                while let Some(&(j, _)) = chars.peek() {
+                    if j < next {
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+
+                last = next;
+            }
+            // a backslash-escaped character is never an expansion
+            '\\' => {
+                chars.next();
+            }
             '\'' => {
                 chars.find(|(_, c)| *c == '\'');
             }
@@ -111,246 +418,752 @@ fn expand_vars(source: &str) -> Result<String, ArgumentError> {
     Ok(expanded)
 }
 
-/// Split a line into its individual words.
-fn split_words(source: &str) -> Vec<&str> {
-    let mut words = vec![];
-    let mut chars = source.chars().enumerate();
-    let mut last = 0;
+/// Expand `pattern` against the filesystem. If nothing on disk matches, bash
+/// passes a glob with no hits through literally, so `pattern` itself is
+/// returned rather than an empty list.
+fn glob_paths(pattern: &str) -> Vec<String> {
+    let found: Vec<String> = match glob::glob(pattern) {
+        Ok(paths) => paths
+            .filter_map(|r| match r {
+                Ok(p) => Some(p.to_string_lossy().to_string()),
+                Err(_) => None,
+            })
+            .collect(),
+        Err(_) => vec![],
+    };
+
+    if found.is_empty() {
+        vec![pattern.to_string()]
+    } else {
+        found
+    }
+}
+
+/// Find the `}` matching the `{` opened just before `source[start]`, treating `\{`,
+/// `\}`, and anything inside single or double quotes as literal text rather
+/// than nesting.
+fn find_brace_close(source: &str, start: usize) -> Option<usize> {
+    let mut depth = 1;
+    let mut chars = source[start..].char_indices();
 
     while let Some((i, c)) = chars.next() {
         match c {
-            ' ' | '\t' => {
-                if last == i {
-                    last += 1;
-                } else {
-                    words.push(&source[last..i]);
-                    last = i + 1;
+            '\\' => {
+                chars.next();
+            }
+            '\'' => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
                 }
             }
-            '\'' | '"' => {
-                argv::find_with_previous(
-                    &mut chars,
-                    |o| {
-                        if let Some((_, c)) = o {
-                            *c != '\\'
-                        } else {
-                            true
+            '"' => {
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => {
+                            chars.next();
                         }
-                    },
-                    |(_, current)| *current == c,
-                );
+                        _ => {}
+                    }
+                }
             }
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return Some(start + i);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Find the first brace group eligible for brace expansion: an unescaped,
+/// unquoted `{` together with its matching `}` (see [find_brace_close]).
+fn find_top_level_brace(source: &str) -> Option<(usize, usize)> {
+    let mut chars = source.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
             '\\' => {
                 chars.next();
             }
+            '\'' => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => {
+                            chars.next();
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            '{' => {
+                if let Some(close) = find_brace_close(source, i + 1) {
+                    return Some((i, close));
+                }
+            }
             _ => {}
         }
     }
 
-    if last < source.len() {
-        words.push(&source[last..])
+    None
+}
+
+/// Split `content` on every occurrence of `sep` that sits outside any nested
+/// `{}` group.
+fn split_top_level<'a>(content: &'a str, sep: &str) -> Vec<&'a str> {
+    let mut parts = vec![];
+    let mut depth = 0i32;
+    let mut last = 0;
+    let mut i = 0;
+
+    while i < content.len() {
+        let c = content[i..].chars().next().unwrap();
+
+        if c == '{' {
+            depth += 1;
+        } else if c == '}' {
+            depth -= 1;
+        } else if depth == 0 && content[i..].starts_with(sep) {
+            parts.push(&content[last..i]);
+            i += sep.len();
+            last = i;
+            continue;
+        }
+
+        i += c.len_utf8();
+    }
+
+    parts.push(&content[last..]);
+
+    parts
+}
+
+/// Parse a signed, optionally zero-padded integer, returning its value and
+/// the total width (including sign) it should be zero-padded back out to -
+/// `0` if the original had no leading zero.
+fn parse_padded_int(s: &str) -> Option<(i64, usize)> {
+    let s = s.trim();
+
+    let digits = s.strip_prefix('-').unwrap_or(s);
+
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
     }
 
-    words
-}
+    let value: i64 = s.parse().ok()?;
+
+    let width = if digits.len() > 1 && digits.starts_with('0') {
+        s.len()
+    } else {
+        0
+    };
+
+    Some((value, width))
+}
+
+/// Render `v` zero-padded out to `width` (including its sign), or plainly if
+/// `width` is `0`.
+fn format_padded(v: i64, width: usize) -> String {
+    if width == 0 {
+        return v.to_string();
+    }
+
+    if v < 0 {
+        format!("-{:0pad$}", -v, pad = width.saturating_sub(1))
+    } else {
+        format!("{:0pad$}", v, pad = width)
+    }
+}
+
+/// Parse a `{x..y}` or `{x..y..step}` sequence expression: numeric (with
+/// optional zero-padding and step) or single-character alphabetic ranges.
+/// Returns `None` if `content` isn't a valid sequence expression, in which
+/// case the caller should fall back to treating the group as a literal or a
+/// comma list.
+fn parse_range(content: &str) -> Option<Vec<String>> {
+    let parts = split_top_level(content, "..");
+
+    if parts.len() < 2 || parts.len() > 3 {
+        return None;
+    }
+
+    if parts
+        .iter()
+        .all(|p| p.chars().count() == 1 && p.chars().next().unwrap().is_ascii_alphabetic())
+    {
+        let start = parts[0].chars().next().unwrap();
+        let end = parts[1].chars().next().unwrap();
+
+        let mut chars: Vec<char> = if start <= end {
+            (start..=end).collect()
+        } else {
+            (end..=start).rev().collect()
+        };
+
+        if parts.len() == 3 {
+            let step = parts[2].parse::<usize>().ok()?.max(1);
+            chars = chars.into_iter().step_by(step).collect();
+        }
+
+        return Some(chars.into_iter().map(|c| c.to_string()).collect());
+    }
+
+    let nums: Vec<(i64, usize)> = parts
+        .iter()
+        .map(|p| parse_padded_int(p))
+        .collect::<Option<Vec<_>>>()?;
+
+    let (start, width) = nums[0];
+    let (end, _) = nums[1];
+    let step = if nums.len() == 3 {
+        nums[2].0.unsigned_abs().max(1) as i64
+    } else {
+        1
+    };
+
+    let mut values = vec![];
+
+    if start <= end {
+        let mut v = start;
+        while v <= end {
+            values.push(v);
+            v += step;
+        }
+    } else {
+        let mut v = start;
+        while v >= end {
+            values.push(v);
+            v -= step;
+        }
+    }
+
+    Some(values.into_iter().map(|v| format_padded(v, width)).collect())
+}
+
+/// Expand comma lists (`pre{a,b,c}post`) and numeric/character sequence
+/// expressions (`{1..5}`, `{1..10..2}`, `{01..10}`, `{a..e}`) into their
+/// cartesian product with any other brace groups in `source`. A `{...}`
+/// group with no top-level comma and no valid sequence expression is left
+/// as a literal `{` and `}` (its contents are still recursed into, so a
+/// further group nested inside it is still expanded). `\{`/`\}` and braces
+/// inside quotes are always left untouched.
+fn expand_braces(source: &str) -> Result<Vec<String>, ArgumentError> {
+    let (open, close) = match find_top_level_brace(source) {
+        Some(pair) => pair,
+        None => return Ok(vec![source.to_string()]),
+    };
+
+    let prefix = &source[..open];
+    let content = &source[open + 1..close];
+    let suffix = &source[close + 1..];
+
+    let comma_items = split_top_level(content, ",");
+
+    let items = if comma_items.len() > 1 {
+        let mut items = vec![];
+
+        for item in comma_items {
+            items.extend(expand_braces(item)?);
+        }
+
+        items
+    } else if let Some(range_items) = parse_range(content) {
+        range_items
+    } else {
+        expand_braces(content)?
+            .into_iter()
+            .map(|c| format!("{{{}}}", c))
+            .collect()
+    };
+
+    let suffix_expansions = expand_braces(suffix)?;
+
+    let mut result = vec![];
+
+    for item in &items {
+        for s in &suffix_expansions {
+            result.push(format!("{}{}{}", prefix, item, s));
+        }
+    }
+
+    Ok(result)
+}
+
+/// Expands a line of input in a similar order to Bash as described in the
+/// [Shell Expansions](https://www.gnu.org/software/bash/manual/html_node/Shell-Expansions.html)
+/// section of the documentation:
+///
+/// > The order of expansions is: brace expansion; tilde expansion, parameter
+/// and variable expansion, arithmetic expansion, and command substitution
+/// (done in a left-to-right fashion); word splitting; and filename expansion.
+/// >
+/// > On systems that can support it, there is an additional expansion available:
+/// process substitution. This is performed at the same time as tilde,
+/// parameter, variable, and arithmetic expansion and command substitution.
+/// >
+/// > After these expansions are performed, quote characters present in the
+/// original word are removed unless they have been quoted themselves (quote
+/// removal).
+///
+/// Since wrash does not support
+/// [Arithmetic Expansion](https://www.gnu.org/software/bash/manual/html_node/Arithmetic-Expansion.html)
+/// or [Process Substitution](https://www.gnu.org/software/bash/manual/html_node/Process-Substitution.html)
+/// those steps are ignored.
+///
+/// After brace expansion, each resulting line is parsed once by the
+/// [argv::ast] grammar into a `Vec<Word>`; tilde expansion, parameter and
+/// variable expansion, command substitution, filename expansion, and quote
+/// removal then all fall out of walking that tree (see
+/// [crate::argv::ast::Segment]) instead of five independent char-by-char
+/// passes. This is also what fixes tilde only expanding when it is the first
+/// segment of a word, and variable/command substitution being correctly
+/// suppressed inside single quotes.
+pub fn expand(source: &str) -> Result<Vec<String>, ArgumentError> {
+    let mut result = vec![];
+
+    for source in expand_braces(source)? {
+        for word in argv::ast::parse_line(&source)? {
+            let values = evaluate_word(
+                &word,
+                || dirs::home_dir().map(|p| p.to_string_lossy().to_string()),
+                user_home_dir,
+            )?;
+
+            result.extend(values);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Look up `name`'s home directory, for `~name` tilde-prefix expansion.
+fn user_home_dir(name: &str) -> Option<String> {
+    users::get_user_by_name(name).map(|user| user.home_dir().to_string_lossy().to_string())
+}
+
+/// Evaluate a single parsed [argv::ast::Word] into its final argv string(s),
+/// in bash order: tilde, then parameter/variable expansion and command
+/// substitution (both handled by [expand_vars]), then word splitting, then
+/// filename expansion. `home_provider` is only ever invoked for a bare `~`;
+/// `user_home_provider` is only ever invoked for a `~name`. Neither is
+/// consulted unless the [argv::ast::Segment::Tilde] is the first segment of
+/// `word` -- elsewhere it is a literal tilde-prefix, matching bash's
+/// restriction to the start of a word (wrash does not special-case the
+/// `:`-separated assignment contexts bash also expands a tilde-prefix in,
+/// e.g. `PATH=~/bin:~other/bin`).
+///
+/// Word splitting only ever applies to the unquoted output of
+/// [argv::ast::Segment::Var]/[argv::ast::Segment::CommandSub] - see
+/// [split_unquoted_expansion] - since every other segment kind either came
+/// from quotes (which suppress splitting) or can't contain raw whitespace in
+/// the first place (an unescaped space in source text would already have
+/// ended the word before it reached here).
+fn evaluate_word<F, G>(
+    word: &argv::ast::Word,
+    home_provider: F,
+    user_home_provider: G,
+) -> Result<Vec<String>, ArgumentError>
+where
+    F: FnOnce() -> Option<String>,
+    G: Fn(&str) -> Option<String>,
+{
+    use argv::ast::Segment;
+
+    let mut fields: Vec<String> = vec![String::new()];
+    let mut is_glob = false;
+    let mut home_provider = Some(home_provider);
+
+    for (i, segment) in word.segments.iter().enumerate() {
+        match segment {
+            Segment::Tilde(name) if i == 0 => {
+                let home = match name.as_str() {
+                    "" => home_provider.take().unwrap()(),
+                    "+" => env::var("PWD").ok(),
+                    "-" => env::var("OLDPWD").ok(),
+                    name => user_home_provider(name),
+                };
+
+                match home {
+                    Some(home) => fields.last_mut().unwrap().push_str(&home),
+                    None => {
+                        let field = fields.last_mut().unwrap();
+                        field.push('~');
+                        field.push_str(name);
+                    }
+                }
+            }
+            Segment::Tilde(name) => {
+                let field = fields.last_mut().unwrap();
+                field.push('~');
+                field.push_str(name);
+            }
+            Segment::Literal(text) => {
+                if text.contains(|c| matches!(c, '*' | '?' | '[')) {
+                    is_glob = true;
+                }
+
+                fields.last_mut().unwrap().push_str(text);
+            }
+            Segment::SingleQuoted(text) => fields.last_mut().unwrap().push_str(text),
+            Segment::DoubleQuoted(segments) => {
+                fields
+                    .last_mut()
+                    .unwrap()
+                    .push_str(&evaluate_quoted_segments(segments)?);
+            }
+            Segment::Var(raw) | Segment::CommandSub(raw) => {
+                split_unquoted_expansion(&mut fields, &expand_vars(raw)?);
+            }
+            // bash resolves a `\x` with no special meaning to a literal `x`
+            // rather than an error, for any x
+            Segment::Escape(c) => fields.last_mut().unwrap().push(*c),
+        }
+    }
+
+    // a field only ever started to hold whatever came after a splittable
+    // expansion's trailing whitespace, and nothing did, isn't a real
+    // argument - bash drops it rather than emitting a trailing empty word
+    if fields.len() > 1 && fields.last().map(String::is_empty).unwrap_or(false) {
+        fields.pop();
+    }
+
+    if is_glob {
+        Ok(fields.iter().flat_map(|field| glob_paths(field)).collect())
+    } else {
+        Ok(fields)
+    }
+}
+
+/// Fold the already fully-resolved, unquoted text of a variable or command
+/// substitution into `fields` - the argv::ast::Word's fields built up so
+/// far - splitting it on whitespace the way bash's word splitting does.
+/// Text this expansion yields is glued onto whatever's already in `fields`'
+/// last entry (and a following segment glues onto whatever this call leaves
+/// there), exactly like a literal segment would; only *this* expansion's own
+/// leading/trailing whitespace forces a new field, and whitespace-only
+/// output is pure separator with no field of its own.
+fn split_unquoted_expansion(fields: &mut Vec<String>, expanded: &str) {
+    if expanded.is_empty() {
+        return;
+    }
+
+    let leading_ws = expanded.starts_with(char::is_whitespace);
+    let trailing_ws = expanded.ends_with(char::is_whitespace);
+
+    let mut pieces = expanded.split_whitespace().peekable();
+
+    if pieces.peek().is_none() {
+        if !fields.last().unwrap().is_empty() {
+            fields.push(String::new());
+        }
+
+        return;
+    }
+
+    if leading_ws && !fields.last().unwrap().is_empty() {
+        fields.push(String::new());
+    }
+
+    while let Some(piece) = pieces.next() {
+        fields.last_mut().unwrap().push_str(piece);
+
+        if pieces.peek().is_some() {
+            fields.push(String::new());
+        }
+    }
+
+    if trailing_ws {
+        fields.push(String::new());
+    }
+}
+
+/// Evaluate the segments found inside a `"..."` span. The grammar never
+/// produces a [argv::ast::Segment::Tilde] or nested quote segment in here,
+/// but the match stays exhaustive so this keeps compiling if that changes.
+fn evaluate_quoted_segments(segments: &[argv::ast::Segment]) -> Result<String, ArgumentError> {
+    use argv::ast::Segment;
+
+    let mut value = String::new();
+
+    for segment in segments {
+        match segment {
+            Segment::Literal(text) | Segment::SingleQuoted(text) => value.push_str(text),
+            Segment::DoubleQuoted(inner) => value.push_str(&evaluate_quoted_segments(inner)?),
+            Segment::Var(raw) | Segment::CommandSub(raw) => value.push_str(&expand_vars(raw)?),
+            Segment::Escape(c) => value.push(*c),
+            Segment::Tilde(name) => {
+                value.push('~');
+                value.push_str(name);
+            }
+        }
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::argv::ast;
+
+    fn get_resource_path(components: &[&str]) -> PathBuf {
+        vec!["tests", "resources"]
+            .iter()
+            .chain(components.iter())
+            .collect()
+    }
+
+    fn word(source: &str) -> ast::Word {
+        ast::parse_line(source).unwrap().remove(0)
+    }
+
+    mod test_braces {
+        use crate::argv::expand;
+
+        #[test]
+        fn test_no_braces() {
+            let expected = vec!["abcd".to_string()];
+            let actual = expand::expand_braces("abcd").unwrap();
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_comma_list() {
+            let expected = vec![
+                "preapost".to_string(),
+                "prebpost".to_string(),
+                "precpost".to_string(),
+            ];
+            let actual = expand::expand_braces("pre{a,b,c}post").unwrap();
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_adjacent_groups_cartesian_product() {
+            let expected = vec![
+                "a1".to_string(),
+                "a2".to_string(),
+                "b1".to_string(),
+                "b2".to_string(),
+            ];
+            let actual = expand::expand_braces("{a,b}{1,2}").unwrap();
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_numeric_range() {
+            let expected = vec!["1", "2", "3", "4", "5"];
+            let actual = expand::expand_braces("{1..5}").unwrap();
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_numeric_range_with_step() {
+            let expected = vec!["1", "3", "5", "7", "9"];
+            let actual = expand::expand_braces("{1..10..2}").unwrap();
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_zero_padded_range() {
+            let expected: Vec<String> = (1..=10).map(|n| format!("{:02}", n)).collect();
+            let actual = expand::expand_braces("{01..10}").unwrap();
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_character_range() {
+            let expected = vec!["a", "b", "c", "d", "e"];
+            let actual = expand::expand_braces("{a..e}").unwrap();
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_no_comma_or_range_is_literal() {
+            let expected = vec!["{justtext}".to_string()];
+            let actual = expand::expand_braces("{justtext}").unwrap();
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_empty_braces_are_literal() {
+            let expected = vec!["pre{}post".to_string()];
+            let actual = expand::expand_braces("pre{}post").unwrap();
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_nested_group_still_expands_inside_literal_outer() {
+            let expected = vec!["{onlyahere}".to_string(), "{onlybhere}".to_string()];
+            let actual = expand::expand_braces("{only{a,b}here}").unwrap();
 
-fn is_pattern(s: &str) -> bool {
-    let mut chars = s.chars();
+            assert_eq!(expected, actual);
+        }
 
-    while let Some(c) = chars.next() {
-        match c {
-            '?' | '*' | '[' => return true,
-            '\\' => {
-                chars.next();
-            }
-            _ => {}
+        #[test]
+        fn test_escaped_braces_are_literal() {
+            let expected = vec!["\\{a,b\\}".to_string()];
+            let actual = expand::expand_braces("\\{a,b\\}").unwrap();
+
+            assert_eq!(expected, actual);
         }
-    }
 
-    false
-}
+        #[test]
+        fn test_quoted_braces_are_literal() {
+            let expected = vec!["'{a,b}'".to_string()];
+            let actual = expand::expand_braces("'{a,b}'").unwrap();
 
-/// Check each arg in `argv` and if it contains any of `*`, `?`, or `[` it is
-/// regarded as a glob and will be expanded. If there are matches to the
-/// pattern on the filesystem, the raw pattern string will be returned.
-fn expand_filenames(argv: Vec<&str>) -> Vec<String> {
-    let mut expanded = vec![];
-
-    for arg in argv {
-        if !is_pattern(arg) {
-            expanded.push(arg.to_string());
-        } else if let Ok(paths) = glob::glob(arg) {
-            let mut found: Vec<String> = paths
-                .filter_map(|r| match r {
-                    Ok(p) => Some(p.to_string_lossy().to_string()),
-                    Err(_) => None,
-                })
-                .collect();
-
-            if found.is_empty() {
-                expanded.push(arg.to_string());
-            } else {
-                expanded.append(&mut found);
-            }
+            assert_eq!(expected, actual);
         }
     }
 
-    expanded
-}
+    mod test_tilde {
+        use super::word;
+        use crate::argv::expand;
+        use std::env;
 
-/// Remove all non-escaped strings.
-fn expand_quotes(argv: Vec<String>) -> Result<Vec<String>, ArgumentError> {
-    let mut words = vec![];
+        #[test]
+        fn test_simple() {
+            let expected = Ok(vec!["HOME".to_string()]);
+            let actual =
+                expand::evaluate_word(&word("~"), || Some("HOME".to_string()), |_| None);
 
-    for word in argv {
-        let mut expanded = String::new();
-        let mut chars = word.chars();
+            assert_eq!(expected, actual);
+        }
 
-        let mut is_single_quote = false;
-        let mut is_double_quote = false;
+        #[test]
+        fn test_simple_with_child() {
+            let expected = Ok(vec!["HOME/a".to_string()]);
+            let actual =
+                expand::evaluate_word(&word("~/a"), || Some("HOME".to_string()), |_| None);
 
-        while let Some(c) = chars.next() {
-            match c {
-                '\'' => {
-                    if !is_double_quote {
-                        is_single_quote = !is_single_quote;
-                    } else {
-                        expanded.push(c);
-                    }
-                }
-                '"' => {
-                    if !is_single_quote {
-                        is_double_quote = !is_double_quote;
-                    } else {
-                        expanded.push(c);
-                    }
-                }
-                '\\' => {
-                    if let Some(c) = chars.next() {
-                        if matches!(c, '"' | '\'' | ' ' | '~') {
-                            expanded.push(c);
-                        } else {
-                            return Err(ArgumentError::InvalidEscape(c));
-                        }
-                    } else {
-                        return Err(ArgumentError::UnexpectedEndOfLine);
-                    }
-                }
-                _ => expanded.push(c),
-            }
+            assert_eq!(expected, actual);
         }
 
-        if is_single_quote {
-            return Err(ArgumentError::UnterminatedSequence('\''))
-        } else if is_double_quote {
-            return Err(ArgumentError::UnterminatedSequence('\''))
-        } else {
-            words.push(expanded);
-        }
-    }
+        #[test]
+        fn test_quoted() {
+            let expected = Ok(vec!["~/a".to_string()]);
+            let actual =
+                expand::evaluate_word(&word("'~'/a"), || Some("HOME".to_string()), |_| None);
 
-    Ok(words)
-}
+            assert_eq!(expected, actual);
+        }
 
-/// Expands a line of input in a similar order to Bash as described in the
-/// [Shell Expansions](https://www.gnu.org/software/bash/manual/html_node/Shell-Expansions.html)
-/// section of the documentation:
-///
-/// > The order of expansions is: brace expansion; tilde expansion, parameter
-/// and variable expansion, arithmetic expansion, and command substitution
-/// (done in a left-to-right fashion); word splitting; and filename expansion.
-/// >
-/// > On systems that can support it, there is an additional expansion available:
-/// process substitution. This is performed at the same time as tilde,
-/// parameter, variable, and arithmetic expansion and command substitution.
-/// >
-/// > After these expansions are performed, quote characters present in the
-/// original word are removed unless they have been quoted themselves (quote
-/// removal).
-///
-/// Since wrash does not support [Brace Expansion](https://www.gnu.org/software/bash/manual/html_node/Brace-Expansion.html),
-/// [Command Substitution](https://www.gnu.org/software/bash/manual/html_node/Command-Substitution.html),
-/// [Arithmetic Expansion](https://www.gnu.org/software/bash/manual/html_node/Arithmetic-Expansion.html),
-/// or [Process Substitution](https://www.gnu.org/software/bash/manual/html_node/Process-Substitution.html)
-/// those steps are ignored.
-///
-/// todo: validate sequences and escapes before expanding
-/// todo: tilde (~) expansion
-/// todo: variable / parameter expansion
-/// todo: word splitting
-/// todo: filename expansion
-pub fn expand(source: &str) -> Result<Vec<String>, ArgumentError> {
-    let tilde = expand_tilde(source, || {
-        dirs::home_dir().map(|p| p.to_string_lossy().to_string())
-    })?;
+        #[test]
+        fn test_mid_word_is_literal() {
+            let expected = Ok(vec!["a~b".to_string()]);
+            let actual =
+                expand::evaluate_word(&word("a~b"), || Some("HOME".to_string()), |_| None);
 
-    let variable = expand_vars(tilde.as_str())?;
+            assert_eq!(expected, actual);
+        }
 
-    let words = split_words(variable.as_str());
+        #[test]
+        fn test_escaped() {
+            let expected = Ok(vec!["~/a".to_string()]);
+            let actual =
+                expand::evaluate_word(&word("\\~/a"), || Some("HOME".to_string()), |_| None);
 
-    let filenames = expand_filenames(words);
+            assert_eq!(expected, actual);
+        }
 
-    let quotes = expand_quotes(filenames)?;
+        #[test]
+        fn test_no_home() {
+            let expected = Ok(vec!["~".to_string()]);
+            let actual = expand::evaluate_word(&word("~"), || None, |_| None);
 
-    Ok(quotes)
-}
+            assert_eq!(expected, actual)
+        }
 
-#[cfg(test)]
-mod test {
-    use std::path::PathBuf;
+        #[test]
+        fn test_named_user() {
+            let expected = Ok(vec!["/home/other".to_string()]);
+            let actual = expand::evaluate_word(&word("~other"), || None, |name| {
+                assert_eq!("other", name);
 
-    fn get_resource_path(components: &[&str]) -> PathBuf {
-        vec!["tests", "resources"]
-            .iter()
-            .chain(components.iter())
-            .collect()
-    }
+                Some("/home/other".to_string())
+            });
 
-    mod test_tilde {
-        use crate::argv::expand;
+            assert_eq!(expected, actual);
+        }
 
         #[test]
-        fn test_simple() {
-            let expected = Ok("HOME".to_string());
-            let actual = expand::expand_tilde("~", || Some("HOME".to_string()));
+        fn test_named_user_with_child() {
+            let expected = Ok(vec!["/home/other/a".to_string()]);
+            let actual = expand::evaluate_word(&word("~other/a"), || None, |_| {
+                Some("/home/other".to_string())
+            });
 
             assert_eq!(expected, actual);
         }
 
         #[test]
-        fn test_simple_with_child() {
-            let expected = Ok("HOME/a".to_string());
-            let actual = expand::expand_tilde("~/a", || Some("HOME".to_string()));
+        fn test_unknown_user_is_left_unexpanded() {
+            let expected = Ok(vec!["~other".to_string()]);
+            let actual = expand::evaluate_word(&word("~other"), || None, |_| None);
 
             assert_eq!(expected, actual);
         }
 
         #[test]
-        fn test_quoted() {
-            let expected = Ok("'~'/a".to_string());
-            let actual = expand::expand_tilde("'~'/a", || Some("HOME".to_string()));
+        fn test_plus_expands_to_pwd() {
+            env::set_var("PWD", "/current/dir");
+
+            let expected = Ok(vec!["/current/dir".to_string()]);
+            let actual = expand::evaluate_word(&word("~+"), || None, |_| None);
 
             assert_eq!(expected, actual);
         }
 
         #[test]
-        fn test_escaped() {
-            let expected = Ok("~/a".to_string());
-            let actual = expand::expand_tilde("\\~/a", || Some("HOME".to_string()));
+        fn test_minus_expands_to_oldpwd() {
+            env::set_var("OLDPWD", "/previous/dir");
+
+            let expected = Ok(vec!["/previous/dir".to_string()]);
+            let actual = expand::evaluate_word(&word("~-"), || None, |_| None);
 
             assert_eq!(expected, actual);
         }
 
         #[test]
-        fn test_no_home() {
-            let expected = Ok("~".to_string());
-            let actual = expand::expand_tilde("~", || None);
+        fn test_mid_word_named_user_is_literal() {
+            let expected = Ok(vec!["a~other".to_string()]);
+            let actual = expand::evaluate_word(&word("a~other"), || None, |_| {
+                Some("/home/other".to_string())
+            });
 
-            assert_eq!(expected, actual)
+            assert_eq!(expected, actual);
         }
     }
 
     mod test_vars {
+        use crate::argv::error::{ArgumentError, Span};
         use crate::argv::expand;
         use std::env;
 
@@ -432,147 +1245,228 @@ mod test {
 
             assert_eq!(expected, actual);
         }
-    }
 
-    mod test_word_split {
-        use crate::argv::expand;
+        #[test]
+        fn test_length() {
+            env::set_var("CHUNK3_1_LEN", "abcde");
+
+            let expected = Ok("5".to_string());
+            let actual = expand::expand_vars("${#CHUNK3_1_LEN}");
+
+            assert_eq!(expected, actual);
+        }
 
         #[test]
-        fn test_one_word() {
-            let expected = vec!["a"];
-            let actual = expand::split_words("a");
+        fn test_default_used_when_unset() {
+            env::remove_var("CHUNK3_1_UNSET");
+
+            let expected = Ok("fallback".to_string());
+            let actual = expand::expand_vars("${CHUNK3_1_UNSET:-fallback}");
 
             assert_eq!(expected, actual);
         }
 
         #[test]
-        fn test_with_space() {
-            let expected = vec!["a", "b"];
-            let actual = expand::split_words("a b");
+        fn test_default_ignored_when_set() {
+            env::set_var("CHUNK3_1_SET", "value");
+
+            let expected = Ok("value".to_string());
+            let actual = expand::expand_vars("${CHUNK3_1_SET:-fallback}");
 
             assert_eq!(expected, actual);
         }
 
         #[test]
-        fn test_with_tab() {
-            let expected = vec!["a", "b"];
-            let actual = expand::split_words("a\tb");
+        fn test_assign_default() {
+            env::remove_var("CHUNK3_1_ASSIGN");
+
+            let expected = Ok("assigned".to_string());
+            let actual = expand::expand_vars("${CHUNK3_1_ASSIGN:=assigned}");
 
             assert_eq!(expected, actual);
+            assert_eq!(Ok("assigned".to_string()), env::var("CHUNK3_1_ASSIGN"));
         }
 
         #[test]
-        fn test_with_trailing_delimiter() {
-            let expected = vec!["a", "b"];
-            let actual = expand::split_words("a b ");
+        fn test_error_when_unset() {
+            env::remove_var("CHUNK3_1_ERR");
+
+            let source = "${CHUNK3_1_ERR:?is required}";
+            let expected = Err(ArgumentError::Custom(
+                "is required".to_string(),
+                Span::new(0, source.len()),
+            ));
+            let actual = expand::expand_vars(source);
 
             assert_eq!(expected, actual);
         }
 
         #[test]
-        fn test_with_extra_delimiter() {
-            let expected = vec!["a", "b"];
-            let actual = expand::split_words("a  b");
+        fn test_alternate_used_when_set() {
+            env::set_var("CHUNK3_1_ALT", "value");
+
+            let expected = Ok("alternate".to_string());
+            let actual = expand::expand_vars("${CHUNK3_1_ALT:+alternate}");
 
             assert_eq!(expected, actual);
         }
 
         #[test]
-        fn test_ignore_in_quotes() {
-            let expected = vec!["'a b'"];
-            let actual = expand::split_words("'a b'");
+        fn test_alternate_empty_when_unset() {
+            env::remove_var("CHUNK3_1_ALT_UNSET");
+
+            let expected = Ok("".to_string());
+            let actual = expand::expand_vars("${CHUNK3_1_ALT_UNSET:+alternate}");
 
             assert_eq!(expected, actual);
         }
 
         #[test]
-        fn test_ignore_escaped() {
-            let expected = vec!["a\\ b"];
-            let actual = expand::split_words("a\\ b");
+        fn test_substring_offset_and_length() {
+            env::set_var("CHUNK3_1_SUB", "abcdefgh");
+
+            let expected = Ok("cde".to_string());
+            let actual = expand::expand_vars("${CHUNK3_1_SUB:2:3}");
 
             assert_eq!(expected, actual);
         }
-    }
 
-    mod test_filename {
-        use crate::argv::expand;
-        use crate::argv::expand::test::get_resource_path;
-        use std::env;
+        #[test]
+        fn test_substring_negative_offset_reads_as_default() {
+            // a bare `:-3` is ambiguous with the `:-word` default operator;
+            // like bash, a literal `-` right after `:` is treated as the
+            // default operator's word, not a negative substring offset
+            env::set_var("CHUNK3_1_SUB_NEG_DEFAULT", "abcdefgh");
+
+            let expected = Ok("abcdefgh".to_string());
+            let actual = expand::expand_vars("${CHUNK3_1_SUB_NEG_DEFAULT:-3}");
+
+            assert_eq!(expected, actual);
+        }
 
-        #[ignore]
         #[test]
-        fn test_existing_glob() -> Result<(), Box<dyn std::error::Error>> {
-            let args = vec!["a*file"];
+        fn test_substring_negative_offset_with_space() {
+            env::set_var("CHUNK3_1_SUB_NEG_SPACE", "abcdefgh");
 
-            let old_cwd = env::current_dir()?;
-            let new_cwd = get_resource_path(&["a_directory"]);
+            let expected = Ok("fgh".to_string());
+            let actual = expand::expand_vars("${CHUNK3_1_SUB_NEG_SPACE: -3}");
 
-            let expected = vec!["a_file".to_string(), "another_file".to_string()];
+            assert_eq!(expected, actual);
+        }
 
-            env::set_current_dir(new_cwd)?;
-            let actual = expand::expand_filenames(args);
-            env::set_current_dir(old_cwd)?;
+        #[test]
+        fn test_remove_shortest_prefix() {
+            env::set_var("CHUNK3_1_PREFIX", "foo/bar/baz");
+
+            let expected = Ok("bar/baz".to_string());
+            let actual = expand::expand_vars("${CHUNK3_1_PREFIX#*/}");
 
             assert_eq!(expected, actual);
+        }
 
-            Ok(())
+        #[test]
+        fn test_remove_shortest_suffix() {
+            env::set_var("CHUNK3_1_SUFFIX", "foo/bar/baz");
+
+            let expected = Ok("foo/bar".to_string());
+            let actual = expand::expand_vars("${CHUNK3_1_SUFFIX%/*}");
+
+            assert_eq!(expected, actual);
         }
+    }
+
+    mod test_substitution {
+        use crate::argv::expand;
 
-        #[ignore]
         #[test]
-        fn test_escaped_glob_no_existing() -> Result<(), Box<dyn std::error::Error>> {
-            let args = vec!["a\\*file"];
+        fn test_paren_substitution() {
+            let expected = Ok("hi".to_string());
+            let actual = expand::expand_vars("$(echo hi)");
 
-            let old_cwd = env::current_dir()?;
-            let new_cwd = get_resource_path(&["a_directory"]);
+            assert_eq!(expected, actual);
+        }
 
-            let expected = vec!["a\\*file".to_string()];
+        #[test]
+        fn test_backtick_substitution() {
+            let expected = Ok("hi".to_string());
+            let actual = expand::expand_vars("`echo hi`");
 
-            env::set_current_dir(new_cwd)?;
-            let actual = expand::expand_filenames(args);
-            env::set_current_dir(old_cwd)?;
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_strips_trailing_newlines() {
+            let expected = Ok("a\nb".to_string());
+            let actual = expand::expand_vars("$(printf 'a\\nb\\n\\n')");
 
             assert_eq!(expected, actual);
+        }
 
-            Ok(())
+        #[test]
+        fn test_substitution_with_surrounding_text() {
+            let expected = Ok("say hi!".to_string());
+            let actual = expand::expand_vars("say $(echo hi)!");
+
+            assert_eq!(expected, actual);
         }
 
-        #[ignore]
         #[test]
-        fn test_no_existing_glob() -> Result<(), Box<dyn std::error::Error>> {
-            let args = vec!["b*file"];
+        fn test_substitution_inside_double_quotes() {
+            let expected = Ok("\"hi\"".to_string());
+            let actual = expand::expand_vars("\"$(echo hi)\"");
 
-            let old_cwd = env::current_dir()?;
-            let new_cwd = get_resource_path(&["a_directory"]);
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_substitution_literal_inside_single_quotes() {
+            let expected = Ok("'$(echo hi)'".to_string());
+            let actual = expand::expand_vars("'$(echo hi)'");
 
-            let expected = vec!["b*file".to_string()];
+            assert_eq!(expected, actual);
+        }
 
-            env::set_current_dir(new_cwd)?;
-            let actual = expand::expand_filenames(args);
-            env::set_current_dir(old_cwd)?;
+        #[test]
+        fn test_nested_paren_substitution() {
+            let expected = Ok("a".to_string());
+            let actual = expand::expand_vars("$(echo $(echo a))");
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_backtick_escape_is_literal() {
+            let expected = Ok("`a".to_string());
+            let actual = expand::expand_vars("`echo \\`a`");
 
             assert_eq!(expected, actual);
+        }
 
-            Ok(())
+        #[test]
+        fn test_non_zero_exit_is_an_error() {
+            let actual = expand::expand_vars("$(false)");
+
+            assert!(actual.is_err());
         }
     }
 
     mod test_quotes {
-        use crate::argv::error::ArgumentError;
+        use super::word;
+        use crate::argv::ast;
         use crate::argv::expand;
 
         #[test]
-        fn test_expand_single() {
+        fn test_single_quote_removal() {
             let expected = Ok(vec!["abc".to_string()]);
-            let actual = expand::expand_quotes(vec!["a'b'c".to_string()]);
+            let actual = expand::evaluate_word(&word("a'b'c"), || None, |_| None);
 
             assert_eq!(expected, actual);
         }
 
         #[test]
-        fn test_expand_double() {
+        fn test_double_quote_removal() {
             let expected = Ok(vec!["abc".to_string()]);
-            let actual = expand::expand_quotes(vec!["a\"b\"c".to_string()]);
+            let actual = expand::evaluate_word(&word("a\"b\"c"), || None, |_| None);
 
             assert_eq!(expected, actual);
         }
@@ -580,7 +1474,7 @@ mod test {
         #[test]
         fn test_single_quote_inside_double() {
             let expected = Ok(vec!["a'bc".to_string()]);
-            let actual = expand::expand_quotes(vec!["a\"'\"bc".to_string()]);
+            let actual = expand::evaluate_word(&word("a\"'\"bc"), || None, |_| None);
 
             assert_eq!(expected, actual);
         }
@@ -588,7 +1482,7 @@ mod test {
         #[test]
         fn test_single_escaped_quote_inside_double() {
             let expected = Ok(vec!["a\"bc".to_string()]);
-            let actual = expand::expand_quotes(vec!["a\"\\\"\"bc".to_string()]);
+            let actual = expand::evaluate_word(&word("a\"\\\"\"bc"), || None, |_| None);
 
             assert_eq!(expected, actual);
         }
@@ -596,23 +1490,94 @@ mod test {
         #[test]
         fn test_expand_escaped_quote() {
             let expected = Ok(vec!["a'bc".to_string()]);
-            let actual = expand::expand_quotes(vec!["a\\'bc".to_string()]);
+            let actual = expand::evaluate_word(&word("a\\'bc"), || None, |_| None);
 
             assert_eq!(expected, actual);
         }
 
         #[test]
-        fn test_quoted_glob_character() {
+        fn test_quoted_glob_character_is_literal() {
             let expected = Ok(vec!["a*b".to_string()]);
-            let actual = expand::expand_quotes(vec!["a'*'b".to_string()]);
+            let actual = expand::evaluate_word(&word("a'*'b"), || None, |_| None);
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_escape_with_no_special_meaning_is_literal() {
+            let expected = Ok(vec!["a$b\\c`d".to_string()]);
+            let actual = expand::evaluate_word(&word("a\\$b\\\\c\\`d"), || None, |_| None);
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_unterminated_quote_is_a_parse_error() {
+            let actual = ast::parse_line("cmd a 'b c");
+
+            assert!(actual.is_err());
+        }
+    }
+
+    mod test_word_splitting {
+        use super::word;
+        use crate::argv::expand;
+        use std::env;
+
+        #[test]
+        fn test_command_substitution_with_no_whitespace_is_one_field() {
+            let expected = Ok(vec!["hi".to_string()]);
+            let actual = expand::evaluate_word(&word("$(echo hi)"), || None, |_| None);
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_command_substitution_splits_on_whitespace() {
+            let expected = Ok(vec!["a".to_string(), "b".to_string()]);
+            let actual = expand::evaluate_word(&word("$(echo a b)"), || None, |_| None);
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_leading_literal_glues_to_first_field() {
+            let expected = Ok(vec!["prea".to_string(), "b".to_string()]);
+            let actual = expand::evaluate_word(&word("pre$(echo a b)"), || None, |_| None);
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_trailing_literal_glues_to_last_field() {
+            let expected = Ok(vec!["a".to_string(), "bpost".to_string()]);
+            let actual = expand::evaluate_word(&word("$(echo a b)post"), || None, |_| None);
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_surrounding_literal_glues_to_first_and_last_field() {
+            let expected = Ok(vec!["prea".to_string(), "bpost".to_string()]);
+            let actual = expand::evaluate_word(&word("pre$(echo a b)post"), || None, |_| None);
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_var_splits_on_whitespace() {
+            env::set_var("CHUNK3_3_SPLIT", "a b");
+
+            let expected = Ok(vec!["a".to_string(), "b".to_string()]);
+            let actual = expand::evaluate_word(&word("$CHUNK3_3_SPLIT"), || None, |_| None);
 
             assert_eq!(expected, actual);
         }
 
         #[test]
-        fn test_unterminated_quote() {
-            let expected = Err(ArgumentError::UnterminatedSequence('\''));
-            let actual = expand::expand_quotes(vec!["cmd a 'b c".to_string()]);
+        fn test_double_quoted_substitution_is_not_split() {
+            let expected = Ok(vec!["a b".to_string()]);
+            let actual = expand::evaluate_word(&word("\"$(echo a b)\""), || None, |_| None);
 
             assert_eq!(expected, actual);
         }
@@ -711,5 +1676,41 @@ mod test {
 
             Ok(())
         }
+
+        #[test]
+        fn test_expand_runs_brace_expansion_before_tokenizing() -> Result<(), Box<dyn std::error::Error>> {
+            let source = "pre{a,b}post";
+
+            let expected = vec!["preapost".to_string(), "prebpost".to_string()];
+            let actual = expand::expand(source)?;
+
+            assert_eq!(expected, actual);
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_expand_splits_unquoted_command_substitution() -> Result<(), Box<dyn std::error::Error>> {
+            let source = "run $(echo a b)";
+
+            let expected = vec!["run".to_string(), "a".to_string(), "b".to_string()];
+            let actual = expand::expand(source)?;
+
+            assert_eq!(expected, actual);
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_expand_quoted_command_substitution_is_not_split() -> Result<(), Box<dyn std::error::Error>> {
+            let source = "run \"$(echo a b)\"";
+
+            let expected = vec!["run".to_string(), "a b".to_string()];
+            let actual = expand::expand(source)?;
+
+            assert_eq!(expected, actual);
+
+            Ok(())
+        }
     }
 }
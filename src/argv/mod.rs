@@ -1,5 +1,124 @@
+use std::collections::VecDeque;
+
+pub mod alias;
+mod ast;
 pub mod error;
 pub mod expand;
+pub mod split;
+
+/// Characters which, if present in an argument, force [join] to single-quote it.
+const SPECIAL_CHARS: &[char] = &[
+    ' ', '\t', '\n', '\'', '"', '\\', '$', '`', '*', '?', ';', '&', '|', '<', '>', '(', ')', '#',
+];
+
+/// Join `args` back into a single shell-safe command line: the inverse of
+/// [split::split_words]. Arguments containing no characters special to the
+/// tokenizer are emitted unchanged; everything else is wrapped in single
+/// quotes, with any embedded single quote escaped as `'\''`. An empty
+/// argument is emitted as `''` so it survives the round trip.
+pub fn join<I, S>(args: I) -> String
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+{
+    args.into_iter()
+        .map(|arg| quote(arg.as_ref()))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn quote(arg: &str) -> String {
+    if arg.is_empty() {
+        return "''".to_string();
+    }
+
+    if !arg.chars().any(|c| SPECIAL_CHARS.contains(&c)) {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('\'');
+
+    for c in arg.chars() {
+        if c == '\'' {
+            quoted.push_str("'\\''");
+        } else {
+            quoted.push(c);
+        }
+    }
+
+    quoted.push('\'');
+
+    quoted
+}
+
+/// Extension trait adding [IteratorWindows::windows], a sliding-window
+/// adapter similar to the unstable `Iterator::map_windows`.
+trait IteratorWindows: Iterator {
+    /// Slide a window of `size` items over `self`, yielding an overlapping
+    /// `Vec` of the last `size` items on each step. Nothing is yielded until
+    /// `size` items have been seen, so a stream shorter than `size` yields
+    /// nothing at all.
+    fn windows(self, size: usize) -> Windows<Self>
+        where
+            Self: Sized,
+    {
+        Windows {
+            iterator: self,
+            size,
+            buffer: VecDeque::with_capacity(size),
+        }
+    }
+}
+
+impl<I: Iterator> IteratorWindows for I {}
+
+/// A sliding window over an iterator's items; see [IteratorWindows::windows].
+struct Windows<I: Iterator> {
+    iterator: I,
+    size: usize,
+    buffer: VecDeque<I::Item>,
+}
+
+impl<I> Iterator for Windows<I>
+    where
+        I: Iterator,
+        I::Item: Clone,
+{
+    type Item = Vec<I::Item>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.size == 0 {
+            return None;
+        }
+
+        if self.buffer.len() == self.size {
+            self.buffer.pop_front();
+        }
+
+        while self.buffer.len() < self.size {
+            self.buffer.push_back(self.iterator.next()?);
+        }
+
+        Some(self.buffer.iter().cloned().collect())
+    }
+}
+
+/// Find the first window of `size` consecutive items for which `predicate`
+/// returns `true`, returning that window's last item. A generalization of
+/// [find_with_previous]'s one-item lookbehind to an arbitrary window size;
+/// see [IteratorWindows::windows].
+pub fn find_with_window<I, F>(iterator: &mut I, size: usize, predicate: F) -> Option<I::Item>
+    where
+        I: Iterator,
+        I::Item: Clone,
+        F: Fn(&[I::Item]) -> bool,
+{
+    iterator
+        .windows(size)
+        .find(|window| predicate(window.as_slice()))
+        .and_then(|mut window| window.pop())
+}
 
 /// Provides much of the same functionality as `Iterator::find` but also
 /// provides the previous value if it exists. Use `previous` to test the
@@ -7,37 +126,76 @@ pub mod expand;
 /// first value). `previous` takes an optional to allow you to specify whether
 /// a `true` return from `current` on the first element will stop any further
 /// iteration or not.
+///
+/// Implemented as the `size == 2` special case of [find_with_window]; see
+/// there for the general form.
 fn find_with_previous<I, F, G>(iterator: &mut I, previous: F, current: G) -> Option<I::Item>
     where
         I: Iterator,
+        I::Item: Clone,
         F: Fn(Option<&I::Item>) -> bool,
         G: Fn(&I::Item) -> bool,
 {
-    match iterator.next() {
-        None => None,
-        Some(current_item) => {
-            if previous(None) && current(&current_item) {
-                Some(current_item)
-            } else {
-                let mut previous_item = current_item;
-
-                // while let Some(current_item) = iterator.next() {
-                for current_item in iterator {
-                    if previous(Some(&previous_item)) && current(&current_item) {
-                        return Some(current_item);
-                    }
-
-                    previous_item = current_item;
-                }
-
-                None
-            }
+    let mut iterator = iterator.peekable();
+
+    if let Some(first) = iterator.peek() {
+        if previous(None) && current(first) {
+            return iterator.next();
         }
     }
+
+    find_with_window(&mut iterator, 2, |window| {
+        previous(Some(&window[0])) && current(&window[1])
+    })
 }
 
 #[cfg(test)]
 mod test {
+    mod join {
+        use crate::argv;
+
+        #[test]
+        fn test_empty_arg() {
+            let expected = "''";
+            let actual = argv::join(&["".to_string()]);
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_plain_args() {
+            let expected = "cmd a b c";
+            let actual = argv::join(&["cmd", "a", "b", "c"]);
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_quotes_whitespace() {
+            let expected = "cmd 'a b'";
+            let actual = argv::join(&["cmd", "a b"]);
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_escapes_embedded_single_quote() {
+            let expected = "'a'\\''b'";
+            let actual = argv::join(&["a'b"]);
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_round_trips_through_split_words() {
+            let args = vec!["cmd".to_string(), "a b".to_string(), "c'd".to_string(), "".to_string()];
+            let joined = argv::join(&args);
+            let actual = argv::split::split_words(&joined).unwrap();
+
+            assert_eq!(args, actual);
+        }
+    }
+
     mod find_with_previous {
         use crate::argv;
 
@@ -97,4 +255,38 @@ mod test {
             assert_eq!(expected, actual);
         }
     }
+
+    mod find_with_window {
+        use crate::argv;
+
+        #[test]
+        fn test_shorter_than_window_yields_nothing() {
+            let mut iter = 0..2;
+
+            let expected = None;
+            let actual = argv::find_with_window(&mut iter, 3, |_| true);
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_finds_matching_window() {
+            let mut iter = 0..10;
+
+            let expected = Some(5);
+            let actual = argv::find_with_window(&mut iter, 3, |window| window == [3, 4, 5]);
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_no_matching_window() {
+            let mut iter = 0..5;
+
+            let expected = None;
+            let actual = argv::find_with_window(&mut iter, 2, |window| window == [10, 11]);
+
+            assert_eq!(expected, actual);
+        }
+    }
 }
\ No newline at end of file
@@ -1,91 +1,183 @@
-use crate::argv::error::ArgumentError;
-use crate::argv;
+use crate::argv::error::{ArgumentError, Span};
+
+/// The state of the tokenizer as it walks the source character-by-character.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    /// Between words, skipping whitespace.
+    Delimiter,
+    Unquoted,
+    SingleQuoted,
+    DoubleQuoted,
+    /// A `\` was the very first character of a word.
+    Backslash,
+    /// A `\` was found mid-word while unquoted.
+    UnquotedBackslash,
+    /// A `\` was found inside a double-quoted word.
+    DoubleQuotedBackslash,
+    /// An unquoted `#` started a word; everything up to the next newline (or
+    /// end of input) is discarded.
+    Comment,
+}
 
-/// An argument splitter which preserves any delimiter and quotes if finds
+/// An argument splitter which preserves any delimiter and quotes it finds.
 /// `Split` internally tracks any errors it finds, and after the first error
-/// is found, any calls to `next` will return `None.
-struct Split<'a> {
+/// is found, any calls to `next` will return `None`.
+///
+/// Walks `source` as an explicit state machine matching POSIX shell
+/// word-splitting rules, so the returned spans are byte-accurate - unlike
+/// the naive offset arithmetic this replaced, which could return the wrong
+/// slice entirely for quoted or escaped words.
+pub struct Split<'a> {
     source: &'a str,
 
     offset: usize,
 
     has_err: bool,
+
+    with_comments: bool,
 }
 
-impl <'a> Split<'a> {
+impl<'a> Split<'a> {
     fn new(source: &'a str) -> Split {
         Split {
             source,
             offset: 0,
             has_err: false,
+            with_comments: true,
         }
     }
 
-    /// Skip all whitespace in `source` while incrementing `offset`.
-    fn skip_whitespace(&mut self) {
-        while let Some(c) = self.source.chars().nth(self.offset) {
-            if ! c.is_whitespace() {
-                break
-            }
-
-            self.offset += 1;
-        }
+    /// Enable or disable `#`-comment stripping (see [split]). Enabled by
+    /// default.
+    pub fn with_comments(mut self, enabled: bool) -> Self {
+        self.with_comments = enabled;
+        self
     }
 }
 
-impl <'a> Iterator for Split<'a> {
+impl<'a> Iterator for Split<'a> {
     type Item = Result<&'a str, ArgumentError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         // if we have already encountered a parsing error we don't need to continue
-        if self.has_err || self.offset >= self.source.len() {
-            return None
+        if self.has_err {
+            return None;
         }
 
-        self.skip_whitespace();
-        let mut chars = self.source.chars();
-
-        if let Some(c) = chars.nth(self.offset) {
-            // check the first character for the first step
-            match c {
-                // todo: combine both quoting branches toigether usine `c` instead o f each quote chracer individually
-                '\'' =>
-                    if let Some(n) = self.source[self.offset + 1..].find(|c| c == '\'') {
-                        let r = Some(Ok(&self.source[self.offset..n + 1]));
-                        self.offset = n + 1;
-
-                        r
-                    } else {
-                        self.has_err = true;
-                        Some(Err(ArgumentError::UnterminatedSequence('\'')))
-                    },
-                '"' =>
-                    if let Some(n) = self.source[self.offset + 1..].find(|c| c == '"') {
-                        let r = Some(Ok(&self.source[self.offset..n + 1]));
-                        self.offset = n + 1;
-
-                        r
-                    } else {
-                        self.has_err = true;
-                        Some(Err(ArgumentError::UnterminatedSequence('"')))
-                    },
-                _ => if let Some((n, _)) = argv::find_with_previous(&mut chars.enumerate(),
-                                                              |o| if let Some((_, c)) = o { *c != '\\' } else { true },
-                                                              |(_, c)| c.is_whitespace()
-                ) {
-                    let r = Some(Ok(&self.source[self.offset..self.offset + n + 1]));
-                    self.offset += n + 1;
-
-                    r
-                } else {
-                    let r = Some(Ok(&self.source[self.offset..]));
-                    self.offset = self.source.len();
-
-                    r
+        let mut state = State::Delimiter;
+        let mut start = self.offset;
+
+        let mut chars = self.source[self.offset..].char_indices();
+
+        loop {
+            match chars.next() {
+                None => {
+                    return match state {
+                        State::Delimiter | State::Comment => None,
+                        State::Unquoted => {
+                            let end = self.source.len();
+                            self.offset = end;
+
+                            Some(Ok(&self.source[start..end]))
+                        }
+                        State::SingleQuoted => {
+                            self.has_err = true;
+
+                            Some(Err(ArgumentError::UnterminatedSequence(
+                                '\'',
+                                Span::new(start, self.source.len()),
+                            )))
+                        }
+                        State::DoubleQuoted => {
+                            self.has_err = true;
+
+                            Some(Err(ArgumentError::UnterminatedSequence(
+                                '"',
+                                Span::new(start, self.source.len()),
+                            )))
+                        }
+                        State::Backslash
+                        | State::UnquotedBackslash
+                        | State::DoubleQuotedBackslash => {
+                            self.has_err = true;
+
+                            Some(Err(ArgumentError::UnexpectedEndOfLine(Span::at(
+                                self.source.len(),
+                            ))))
+                        }
+                    };
+                }
+                Some((rel_offset, c)) => {
+                    let abs_offset = self.offset + rel_offset;
+
+                    match state {
+                        State::Delimiter => {
+                            if c.is_whitespace() {
+                                continue;
+                            }
+
+                            if self.with_comments && c == '#' {
+                                state = State::Comment;
+                                continue;
+                            }
+
+                            start = abs_offset;
+
+                            state = match c {
+                                '\'' => State::SingleQuoted,
+                                '"' => State::DoubleQuoted,
+                                '\\' => State::Backslash,
+                                _ => State::Unquoted,
+                            };
+                        }
+                        // discard everything up to (and including) the next
+                        // newline, which itself switches us back to Delimiter
+                        State::Comment => {
+                            if c == '\n' {
+                                state = State::Delimiter;
+                            }
+                        }
+                        State::Unquoted => {
+                            if c.is_whitespace() {
+                                self.offset = abs_offset;
+
+                                return Some(Ok(&self.source[start..abs_offset]));
+                            }
+
+                            state = match c {
+                                '\'' => State::SingleQuoted,
+                                '"' => State::DoubleQuoted,
+                                '\\' => State::UnquotedBackslash,
+                                _ => State::Unquoted,
+                            };
+                        }
+                        State::SingleQuoted => {
+                            if c == '\'' {
+                                state = State::Unquoted;
+                            }
+                        }
+                        State::DoubleQuoted => {
+                            state = match c {
+                                '"' => State::Unquoted,
+                                '\\' => State::DoubleQuotedBackslash,
+                                _ => State::DoubleQuoted,
+                            };
+                        }
+                        // a backslash only escapes $, `, ", \, and newline inside
+                        // double quotes; any other character leaves the backslash
+                        // in place, but either way the next character is consumed
+                        // and we return to DoubleQuoted
+                        State::DoubleQuotedBackslash => {
+                            state = State::DoubleQuoted;
+                        }
+                        // an unquoted backslash always escapes the following
+                        // character verbatim, whether it began the word or not
+                        State::Backslash | State::UnquotedBackslash => {
+                            state = State::Unquoted;
+                        }
+                    }
                 }
             }
-        } else {
-            None
         }
     }
 }
@@ -93,14 +185,71 @@ impl <'a> Iterator for Split<'a> {
 /// Split a full list of command line arguments into their separate args.
 ///
 /// todo: ideally we could return an `impl iterator` to hide our internal `Split` struct.
-fn split(source: &str) -> Split {
+pub fn split(source: &str) -> Split {
     Split::new(source)
 }
 
+/// Resolve a single raw word yielded by [Split] - which still carries its
+/// surrounding quotes and backslashes - into its final, unescaped form. The
+/// raw word is assumed to already be well-formed (quotes balanced), since it
+/// came from a successful [Split] iteration.
+fn resolve_word(raw: &str) -> String {
+    let mut resolved = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+
+                    resolved.push(c);
+                }
+            }
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some(next) if matches!(next, '$' | '`' | '"' | '\\') => {
+                                resolved.push(next)
+                            }
+                            Some('\n') => { /* line continuation, drop both chars */ }
+                            Some(next) => {
+                                resolved.push('\\');
+                                resolved.push(next);
+                            }
+                            None => {}
+                        },
+                        _ => resolved.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    resolved.push(next);
+                }
+            }
+            _ => resolved.push(c),
+        }
+    }
+
+    resolved
+}
+
+/// Split `source` into fully-resolved argument strings: quotes are removed
+/// and escape sequences are resolved, so the result is ready to hand
+/// straight to a process as `argv`. See [split] for the quote-preserving,
+/// zero-copy variant this is built on.
+pub fn split_words(source: &str) -> Result<Vec<String>, ArgumentError> {
+    split(source).map(|word| word.map(resolve_word)).collect()
+}
+
 #[cfg(test)]
 mod split {
-    use crate::argv;
-    use crate::argv::error::ArgumentError;
+    use crate::argv::error::{ArgumentError, Span};
     use crate::argv::split;
 
     #[test]
@@ -138,6 +287,32 @@ mod split {
         Ok(())
     }
 
+    #[test]
+    fn test_single_quoted_word() -> Result<(), Box<dyn std::error::Error>> {
+        let source = "cmd a 'b c'";
+        let mut actual = split::split(source);
+
+        assert_eq!(Some(Ok("cmd")), actual.next());
+        assert_eq!(Some(Ok("a")), actual.next());
+        assert_eq!(Some(Ok("'b c'")), actual.next());
+        assert_eq!(None, actual.next());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_double_quoted_word() -> Result<(), Box<dyn std::error::Error>> {
+        let source = "cmd \"a b\" c";
+        let mut actual = split::split(source);
+
+        assert_eq!(Some(Ok("cmd")), actual.next());
+        assert_eq!(Some(Ok("\"a b\"")), actual.next());
+        assert_eq!(Some(Ok("c")), actual.next());
+        assert_eq!(None, actual.next());
+
+        Ok(())
+    }
+
     #[test]
     fn test_unterminated_single_string() -> Result<(), Box<dyn std::error::Error>> {
         let source = "cmd a 'b c";
@@ -146,7 +321,10 @@ mod split {
         assert_eq!(Some(Ok("cmd")), actual.next());
         assert_eq!(Some(Ok("a")), actual.next());
         assert_eq!(
-            Some(Err(ArgumentError::UnterminatedSequence('\''))),
+            Some(Err(ArgumentError::UnterminatedSequence(
+                '\'',
+                Span::new(6, 10)
+            ))),
             actual.next()
         );
         assert_eq!(None, actual.next());
@@ -162,7 +340,10 @@ mod split {
         assert_eq!(Some(Ok("cmd")), actual.next());
         assert_eq!(Some(Ok("a")), actual.next());
         assert_eq!(
-            Some(Err(ArgumentError::UnterminatedSequence('\"'))),
+            Some(Err(ArgumentError::UnterminatedSequence(
+                '\"',
+                Span::new(6, 10)
+            ))),
             actual.next()
         );
         assert_eq!(None, actual.next());
@@ -182,4 +363,98 @@ mod split {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_trailing_backslash() -> Result<(), Box<dyn std::error::Error>> {
+        let source = "cmd a\\";
+        let mut actual = split::split(source);
+
+        assert_eq!(Some(Ok("cmd")), actual.next());
+        assert_eq!(
+            Some(Err(ArgumentError::UnexpectedEndOfLine(Span::at(6)))),
+            actual.next()
+        );
+        assert_eq!(None, actual.next());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_words_removes_quotes() {
+        let expected = Ok(vec!["cmd".to_string(), "a b".to_string(), "c".to_string()]);
+        let actual = split::split_words("cmd \"a b\" c");
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_split_words_resolves_escape() {
+        let expected = Ok(vec!["b c".to_string()]);
+        let actual = split::split_words("b\\ c");
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_split_words_single_quotes_are_literal() {
+        let expected = Ok(vec!["a$b".to_string()]);
+        let actual = split::split_words("'a$b'");
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_split_words_propagates_error() {
+        let expected = Err(ArgumentError::UnterminatedSequence('\'', Span::new(4, 6)));
+        let actual = split::split_words("cmd 'a");
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_comment_strips_rest_of_line() -> Result<(), Box<dyn std::error::Error>> {
+        let source = "cmd a # trailing note";
+        let mut actual = split::split(source);
+
+        assert_eq!(Some(Ok("cmd")), actual.next());
+        assert_eq!(Some(Ok("a")), actual.next());
+        assert_eq!(None, actual.next());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_comment_stops_at_newline() -> Result<(), Box<dyn std::error::Error>> {
+        let source = "cmd # note\nb";
+        let mut actual = split::split(source);
+
+        assert_eq!(Some(Ok("cmd")), actual.next());
+        assert_eq!(Some(Ok("b")), actual.next());
+        assert_eq!(None, actual.next());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_mid_word_is_literal() -> Result<(), Box<dyn std::error::Error>> {
+        let source = "foo#bar";
+        let mut actual = split::split(source);
+
+        assert_eq!(Some(Ok("foo#bar")), actual.next());
+        assert_eq!(None, actual.next());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_comments_disabled() -> Result<(), Box<dyn std::error::Error>> {
+        let source = "cmd #not-a-comment";
+        let mut actual = split::split(source).with_comments(false);
+
+        assert_eq!(Some(Ok("cmd")), actual.next());
+        assert_eq!(Some(Ok("#not-a-comment")), actual.next());
+        assert_eq!(None, actual.next());
+
+        Ok(())
+    }
+}
@@ -1,11 +1,17 @@
 use std::env;
-use std::io::Write;
+use std::io::{self, Write};
 use std::path::PathBuf;
 
 use crate::error::StatusError;
+use crate::history::{HistoryEntry, HistoryFormat};
+use crate::session::SessionMode;
 use crate::Session;
 use clap::{Arg, ErrorKind, SubCommand};
 use directories::UserDirs;
+use regex::Regex;
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
 
 type BuiltinResult = Result<(), StatusError>;
 
@@ -34,7 +40,25 @@ macro_rules! handle_matches {
 
 /// Check if a command is a builtin or not.
 pub fn is_builtin(command: &str) -> bool {
-    matches!(command, "exit" | "cd" | "mode" | "help" | "history")
+    matches!(
+        command,
+        "exit"
+            | "cd"
+            | "mode"
+            | "help"
+            | "history"
+            | "export"
+            | "unset"
+            | "alias"
+            | "unalias"
+            | "source"
+            | "jobs"
+            | "fg"
+            | "bg"
+            | "wait"
+            | "watch"
+            | "which"
+    )
 }
 
 /// Exit is a builtin for exiting out of the current shell session.
@@ -73,7 +97,7 @@ pub fn exit(argv: &[String]) -> BuiltinResult {
 }
 
 /// CD is builtin for changing the current working directory in the shell.
-pub fn cd(argv: &[String]) -> BuiltinResult {
+pub fn cd(err_writer: &mut impl Write, session: &mut Session, argv: &[String]) -> BuiltinResult {
     let app = app_from_crate!()
         .name("cd")
         .about("change the current working directory")
@@ -92,27 +116,494 @@ pub fn cd(argv: &[String]) -> BuiltinResult {
     let matches = handle_matches!(app, argv);
 
     let target = if matches.is_present("directory") {
-        PathBuf::from(matches.value_of("directory").unwrap())
+        matches.value_of("directory").unwrap().to_string()
     } else {
         let dirs = match UserDirs::new() {
             Some(dirs) => dirs,
             None => {
-                eprintln!("could not determine the home directory for the current user");
+                write!(
+                    err_writer,
+                    "could not determine the home directory for the current user\n"
+                );
 
                 return Err(StatusError { code: 2 });
             }
         };
 
-        dirs.home_dir().to_path_buf()
+        dirs.home_dir().to_string_lossy().to_string()
     };
 
-    if let Err(err) = std::env::set_current_dir(target) {
-        eprintln!("Error changing directories: {}", err)
+    if let Err(err) = session.change_directory(target.as_str()) {
+        write!(err_writer, "Error changing directories: {}\n", err);
     }
 
     Ok(())
 }
 
+/// Export sets an environment variable for the remainder of the session, or
+/// marks an already-set variable as available to child processes. With no
+/// arguments, the current environment is printed sorted by key.
+pub fn export(out_writer: &mut impl Write, argv: &[String]) -> BuiltinResult {
+    let app = app_from_crate!()
+        .name("export")
+        .about("set or list environment variables")
+        .arg(Arg::with_name("assignment").help(
+            "a 'NAME=value' pair to set, or a bare 'NAME' to export an already-set variable",
+        ));
+
+    let matches = handle_matches!(app, argv);
+
+    let assignment = match matches.value_of("assignment") {
+        Some(assignment) => assignment,
+        None => {
+            let mut vars: Vec<(String, String)> = env::vars().collect();
+            vars.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            for (name, value) in vars {
+                write!(out_writer, "{}={}\n", name, value);
+            }
+
+            return Ok(());
+        }
+    };
+
+    match assignment.split_once('=') {
+        Some((name, value)) => env::set_var(name, value),
+        None => {
+            if env::var(assignment).is_err() {
+                eprintln!("Error: '{}' is not set", assignment);
+
+                return Err(StatusError { code: 1 });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Define or list aliases, expanded by [crate::Session::expand_aliases]
+/// whenever the aliased name leads a command line.
+pub fn alias(out_writer: &mut impl Write, session: &mut Session, argv: &[String]) -> BuiltinResult {
+    let app = app_from_crate!()
+        .name("alias")
+        .about("define or list command aliases")
+        .arg(Arg::with_name("assignment").help(
+            "a 'NAME=value' pair to define, or a bare 'NAME' to print an already-defined alias",
+        ));
+
+    let matches = handle_matches!(app, argv);
+
+    let assignment = match matches.value_of("assignment") {
+        Some(assignment) => assignment,
+        None => {
+            for (name, body) in session.aliases() {
+                write!(out_writer, "{}={}\n", name, body);
+            }
+
+            return Ok(());
+        }
+    };
+
+    match assignment.split_once('=') {
+        Some((name, body)) => session.set_alias(name.to_string(), body.to_string()),
+        None => match session.get_alias(assignment) {
+            Some(body) => {
+                write!(out_writer, "{}={}\n", assignment, body);
+            }
+            None => {
+                eprintln!("Error: '{}' is not aliased", assignment);
+
+                return Err(StatusError { code: 1 });
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Unalias removes a previously defined alias.
+pub fn unalias(session: &mut Session, argv: &[String]) -> BuiltinResult {
+    let app = app_from_crate!()
+        .name("unalias")
+        .about("remove a defined alias")
+        .arg(
+            Arg::with_name("name")
+                .help("the name of the alias to remove")
+                .required(true),
+        );
+
+    let matches = handle_matches!(app, argv);
+
+    session.remove_alias(matches.value_of("name").unwrap());
+
+    Ok(())
+}
+
+/// Unset removes a previously set environment variable.
+pub fn unset(argv: &[String]) -> BuiltinResult {
+    let app = app_from_crate!()
+        .name("unset")
+        .about("remove an environment variable")
+        .arg(
+            Arg::with_name("name")
+                .help("the name of the environment variable to remove")
+                .required(true),
+        );
+
+    let matches = handle_matches!(app, argv);
+
+    env::remove_var(matches.value_of("name").unwrap());
+
+    Ok(())
+}
+
+/// Source reads `file` line by line and feeds each line through the same
+/// command-dispatch path as the interactive loop, so a file can contain
+/// `mode`, `cd`, `export`, or any other builtin invocation.
+///
+/// Lines beginning with `#` are comments and are skipped. A line that fails
+/// prints its error to `err_writer` and sourcing continues with the next
+/// line, unless `--strict` is given, in which case sourcing stops at the
+/// first failure.
+pub fn source(
+    out_writer: &mut impl Write,
+    err_writer: &mut impl Write,
+    session: &mut Session,
+    argv: &[String],
+) -> BuiltinResult {
+    let app = app_from_crate!()
+        .name("source")
+        .about("run the commands in a file as if they were typed at the prompt")
+        .arg(
+            Arg::with_name("file")
+                .help("the file to source")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("strict")
+                .long("strict")
+                .help("stop sourcing as soon as a line fails instead of skipping it"),
+        );
+
+    let matches = handle_matches!(app, argv);
+
+    let path = matches.value_of("file").unwrap();
+    let strict = matches.is_present("strict");
+
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            write!(err_writer, "Error: could not read '{}': {}\n", path, err);
+
+            return Err(StatusError { code: 1 });
+        }
+    };
+
+    let mut should_continue = true;
+
+    for line in contents.lines() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Err(err) = crate::dispatch(
+            line,
+            session,
+            out_writer,
+            err_writer,
+            &mut should_continue,
+        ) {
+            write!(err_writer, "Error: {}\n", err);
+
+            if strict {
+                return Err(StatusError { code: 1 });
+            }
+        }
+
+        if !should_continue {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// List the currently tracked background jobs along with their id, state,
+/// and original command.
+pub fn jobs(
+    out_writer: &mut impl Write,
+    err_writer: &mut impl Write,
+    session: &mut Session,
+    argv: &[String],
+) -> BuiltinResult {
+    let app = app_from_crate!()
+        .name("jobs")
+        .about("list background jobs and their state")
+        .subcommand(
+            SubCommand::with_name("output")
+                .about("show the captured stdout/stderr of a job")
+                .arg(
+                    Arg::with_name("id")
+                        .help("the id of the job to show output for")
+                        .required(true)
+                        .validator(|id| parse_job_id(id.as_str()).map(|_| ())),
+                ),
+        );
+
+    let matches = handle_matches!(app, argv);
+
+    match matches.subcommand() {
+        ("output", Some(sub_matches)) => {
+            let id: usize = sub_matches.value_of("id").unwrap().parse().unwrap();
+
+            match session.jobs().get_mut(id) {
+                Some(job) => {
+                    out_writer.write_all(job.output().as_slice()).ok();
+
+                    Ok(())
+                }
+                None => {
+                    write!(err_writer, "Error: no such job '{}'\n", id);
+
+                    Err(StatusError { code: 1 })
+                }
+            }
+        }
+        _ => {
+            for (id, job) in session.jobs().iter() {
+                write!(out_writer, "[{}] {}\t{}\n", id, job.state, job.command);
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Parse and validate a job id argument shared by `fg`, `bg`, and `wait`.
+fn parse_job_id(id: &str) -> Result<usize, String> {
+    id.parse::<usize>()
+        .map_err(|err| format!("could not parse job id from '{}': {}", id, err))
+}
+
+/// Bring a background job into the foreground, blocking until it finishes
+/// and reaping it.
+pub fn fg(
+    out_writer: &mut impl Write,
+    err_writer: &mut impl Write,
+    session: &mut Session,
+    argv: &[String],
+) -> BuiltinResult {
+    let app = app_from_crate!().name("fg").about("bring a background job into the foreground").arg(
+        Arg::with_name("id")
+            .help("the id of the job to bring to the foreground")
+            .required(true)
+            .validator(|id| parse_job_id(id.as_str()).map(|_| ())),
+    );
+
+    let matches = handle_matches!(app, argv);
+
+    let id: usize = matches.value_of("id").unwrap().parse().unwrap();
+
+    let mut job = match session.jobs().remove(id) {
+        Some(job) => job,
+        None => {
+            write!(err_writer, "Error: no such job '{}'\n", id);
+
+            return Err(StatusError { code: 1 });
+        }
+    };
+
+    write!(out_writer, "{}\n", job.command);
+
+    match job.child.wait() {
+        Ok(status) => {
+            let code = status.code().unwrap_or(255);
+
+            if code == 0 {
+                Ok(())
+            } else {
+                Err(StatusError { code })
+            }
+        }
+        Err(err) => {
+            write!(err_writer, "Error: job '{}' never started: {}\n", id, err);
+
+            Err(StatusError { code: 1 })
+        }
+    }
+}
+
+/// Acknowledge job `id` as running in the background. wrash has no
+/// job-suspension support (no Ctrl-Z/SIGTSTP), so every tracked job is
+/// already running - there is no stopped job for `bg` to actually resume.
+/// This exists for compatibility with scripts/muscle memory from shells
+/// that do support suspending jobs.
+pub fn bg(
+    out_writer: &mut impl Write,
+    err_writer: &mut impl Write,
+    session: &mut Session,
+    argv: &[String],
+) -> BuiltinResult {
+    let app = app_from_crate!()
+        .name("bg")
+        .about("acknowledge a job as running in the background (wrash has no job-suspension support, so there is nothing to actually resume)")
+        .arg(
+            Arg::with_name("id")
+                .help("the id of the job to acknowledge")
+                .required(true)
+                .validator(|id| parse_job_id(id.as_str()).map(|_| ())),
+        );
+
+    let matches = handle_matches!(app, argv);
+
+    let id: usize = matches.value_of("id").unwrap().parse().unwrap();
+
+    match session.jobs().get_mut(id) {
+        Some(job) => {
+            write!(out_writer, "[{}] {}\n", id, job.command);
+
+            Ok(())
+        }
+        None => {
+            write!(err_writer, "Error: no such job '{}'\n", id);
+
+            Err(StatusError { code: 1 })
+        }
+    }
+}
+
+/// Block until one background job, or all of them if no id is given, have
+/// finished. Returns the last reaped child's exit code as the `StatusError`.
+pub fn wait(
+    err_writer: &mut impl Write,
+    session: &mut Session,
+    argv: &[String],
+) -> BuiltinResult {
+    let app = app_from_crate!().name("wait").about("wait for background jobs to finish").arg(
+        Arg::with_name("id")
+            .help("the id of the job to wait for, if omitted all jobs are waited on")
+            .validator(|id| parse_job_id(id.as_str()).map(|_| ())),
+    );
+
+    let matches = handle_matches!(app, argv);
+
+    let ids: Vec<usize> = match matches.value_of("id") {
+        Some(id) => vec![id.parse().unwrap()],
+        None => session.jobs().iter().map(|(id, _)| *id).collect(),
+    };
+
+    let mut last_code = 0;
+
+    for id in ids {
+        let mut job = match session.jobs().remove(id) {
+            Some(job) => job,
+            None => {
+                write!(err_writer, "Error: no such job '{}'\n", id);
+
+                return Err(StatusError { code: 1 });
+            }
+        };
+
+        match job.child.wait() {
+            Ok(status) => last_code = status.code().unwrap_or(255),
+            Err(err) => {
+                write!(err_writer, "Error: job '{}' never started: {}\n", id, err);
+
+                return Err(StatusError { code: 1 });
+            }
+        }
+    }
+
+    if last_code == 0 {
+        Ok(())
+    } else {
+        Err(StatusError { code: last_code })
+    }
+}
+
+/// Re-run a command every time a watched file changes, clearing the screen
+/// before each run. Watches the current directory by default; `--path` may
+/// be given (repeatedly) to watch other paths instead. Runs until the user
+/// sends Ctrl-C.
+pub fn watch(session: &mut Session, argv: &[String]) -> BuiltinResult {
+    let app = app_from_crate!()
+        .name("watch")
+        .about("re-run a command whenever watched files change")
+        .arg(
+            Arg::with_name("path")
+                .long("path")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("a path to watch, may be given more than once; defaults to the current directory"),
+        )
+        .arg(
+            Arg::with_name("command")
+                .help("the command to re-run on every change")
+                .multiple(true)
+                .required(true),
+        );
+
+    let matches = handle_matches!(app, argv);
+
+    let paths: Vec<PathBuf> = matches
+        .values_of("path")
+        .map(|values| values.map(PathBuf::from).collect())
+        .unwrap_or_default();
+
+    let command = matches
+        .values_of("command")
+        .unwrap()
+        .collect::<Vec<&str>>()
+        .join(" ");
+
+    match crate::watch::run(paths.as_slice(), command.as_str(), session) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+
+            Err(StatusError { code: 1 })
+        }
+    }
+}
+
+/// Print the path that would be run for `name` in `mode normal`,
+/// the same resolution `dispatch` uses, so users can see exactly what would
+/// run before they run it.
+pub fn which(
+    out_writer: &mut impl Write,
+    err_writer: &mut impl Write,
+    argv: &[String],
+) -> BuiltinResult {
+    let app = app_from_crate!()
+        .name("which")
+        .about("show the resolved path for a command name")
+        .arg(
+            Arg::with_name("name")
+                .help("the command name to resolve")
+                .required(true),
+        );
+
+    let matches = handle_matches!(app, argv);
+
+    let name = matches.value_of("name").unwrap();
+
+    match crate::resolve_command(name) {
+        Some(path) => {
+            write!(out_writer, "{}\n", path.display());
+
+            Ok(())
+        }
+        None => {
+            write!(err_writer, "{}: command not found\n", name);
+
+            Err(StatusError { code: 127 })
+        }
+    }
+}
+
 /// Print the status of the current node.
 pub fn mode(
     out_writer: &mut impl Write,
@@ -172,18 +663,211 @@ Below is a list of supported builtins, pass '--help' to any o them for more info
     cd         change the current working directory of the shell
     mode       set or modify the current shell execution mode
     ?          show this help text
-    history    show and filter shell command history"
+    history    show and filter shell command history
+    export     set an environment variable, or list the current environment
+    unset      remove an environment variable
+    alias      define or list command aliases
+    unalias    remove a command alias
+    source     run the commands in a file as if they were typed at the prompt
+    jobs       list background jobs and their state
+    fg         bring a background job into the foreground
+    bg         acknowledge a job as running in the background
+    wait       wait for one or all background jobs to finish
+    watch      re-run a command whenever watched files change
+    which      show the resolved path for a command name
+
+Append '&' to a command to run it in the background instead of waiting on it."
     );
 
     Ok(())
 }
 
-/// Examine and manipulate the command history, if the command was run in "wrapped" mode,
+/// Check whether `entry` should be shown given the requested filters.
 ///
-/// todo: show / search commands (allow specifying offset or number)
-/// todo: allow filtering commands with regex
-/// todo: fix filtering on base and on mode (very broken not consistent), it should filter based on the given mode and base
-/// todo: add --builtin && --no-builtin
+/// Builtin entries are hidden by `show_builtin` being false and by the
+/// failure, regex and time filters, but never by the `mode`/`base` filters.
+/// This is
+/// the single predicate used by both the default view and `filter`, so the
+/// two can no longer disagree about what "matches" means.
+fn entry_matches(
+    entry: &HistoryEntry,
+    mode: Option<SessionMode>,
+    base: Option<&str>,
+    regex: Option<&Regex>,
+    since: Option<u64>,
+    until: Option<u64>,
+    show_builtin: bool,
+    show_non_builtin: bool,
+    failed_only: bool,
+) -> bool {
+    if entry.is_builtin {
+        if !show_builtin {
+            return false;
+        }
+    } else {
+        if !show_non_builtin {
+            return false;
+        }
+
+        if let Some(mode) = mode {
+            if entry.mode != mode {
+                return false;
+            }
+        }
+
+        if let (Some(base), Some(entry_base)) = (base, entry.base.as_deref()) {
+            if entry_base != base {
+                return false;
+            }
+        }
+    }
+
+    if failed_only && entry.exit_code == 0 {
+        return false;
+    }
+
+    if let Some(regex) = regex {
+        if !regex.is_match(entry.get_command().as_str()) {
+            return false;
+        }
+    }
+
+    if let Some(since) = since {
+        if entry.timestamp < since {
+            return false;
+        }
+    }
+
+    if let Some(until) = until {
+        if entry.timestamp > until {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Print the entries of `session`'s history that match the given filters,
+/// numbering each line by its absolute position so pages line up across
+/// calls with different `offset`s.
+fn print_entries(
+    out_writer: &mut impl Write,
+    session: &Session,
+    mode: Option<SessionMode>,
+    base: Option<&str>,
+    regex: Option<&Regex>,
+    since: Option<u64>,
+    until: Option<u64>,
+    show_builtin: bool,
+    show_non_builtin: bool,
+    failed_only: bool,
+    offset: usize,
+    count: Option<usize>,
+) {
+    let entries = session
+        .history_iter()
+        .filter(|entry| {
+            entry_matches(
+                entry,
+                mode,
+                base,
+                regex,
+                since,
+                until,
+                show_builtin,
+                show_non_builtin,
+                failed_only,
+            )
+        })
+        .skip(offset);
+
+    let entries: Box<dyn Iterator<Item = &HistoryEntry>> = match count {
+        Some(count) => Box::new(entries.take(count)),
+        None => Box::new(entries),
+    };
+
+    for (i, entry) in entries.enumerate() {
+        write!(out_writer, "{}: {}\n", i + offset, entry.get_command());
+    }
+}
+
+/// Interactively search history like Ctrl-R: read a query a keystroke at a
+/// time, after each keystroke showing the most recent entry whose command
+/// contains it. Enter selects the current match and prints it to
+/// `out_writer`; Ctrl-R jumps to the next older match for the same query.
+fn reverse_search(out_writer: &mut impl Write, session: &Session) -> BuiltinResult {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock().into_raw_mode().unwrap();
+
+    let stdin = io::stdin();
+    let stdin = stdin.lock();
+
+    let entries: Vec<&HistoryEntry> = session.history_iter().rev().collect();
+
+    let mut query = String::new();
+    let mut skip = 0usize;
+
+    let redraw = |stdout: &mut dyn Write, query: &str, matched: Option<&str>| {
+        write!(
+            stdout,
+            "\r{}(reverse-i-search)'{}': {}",
+            termion::clear::CurrentLine,
+            query,
+            matched.unwrap_or(""),
+        )
+        .and_then(|_| stdout.flush())
+    };
+
+    let _ = redraw(&mut stdout, query.as_str(), None);
+
+    for key in stdin.keys().filter_map(Result::ok) {
+        match key {
+            Key::Char('\n') => break,
+            Key::Ctrl('r') => skip += 1,
+            Key::Ctrl('c') => {
+                query.clear();
+                break;
+            }
+            Key::Backspace => {
+                query.pop();
+                skip = 0;
+            }
+            Key::Char(c) => {
+                query.push(c);
+                skip = 0;
+            }
+            _ => { /* do nothing */ }
+        }
+
+        let matched = entries
+            .iter()
+            .filter(|entry| entry.get_command().contains(query.as_str()))
+            .nth(skip);
+
+        let _ = redraw(
+            &mut stdout,
+            query.as_str(),
+            matched.map(|entry| entry.get_command()).as_deref(),
+        );
+    }
+
+    let _ = writeln!(stdout, "\r");
+
+    if !query.is_empty() {
+        let matched = entries
+            .iter()
+            .filter(|entry| entry.get_command().contains(query.as_str()))
+            .nth(skip);
+
+        if let Some(entry) = matched {
+            write!(out_writer, "{}\n", entry.get_command());
+        }
+    }
+
+    Ok(())
+}
+
+/// Examine and manipulate the command history, if the command was run in "wrapped" mode,
 pub fn history(
     out_writer: &mut impl Write,
     err_writer: &mut impl Write,
@@ -194,60 +878,220 @@ pub fn history(
         .name("history")
         .max_term_width(80)
         .about("examine and manipulate the command history, if session is frozen this command wil ALWAYS fail")
-        .after_help("if no subcommand is specified, then only commands run with the same mode and base command  along with builtins are shown")
+        .after_help("if no subcommand is specified, then only commands run with the same mode and base command along with builtins are shown")
+        .arg(
+            Arg::with_name("regex")
+                .long("regex")
+                .visible_alias("grep")
+                .takes_value(true)
+                .global(true)
+                .help("only show commands whose text matches the given regular expression"),
+        )
+        .arg(
+            Arg::with_name("since")
+                .long("since")
+                .takes_value(true)
+                .global(true)
+                .validator(|n| n.parse::<u64>().map(|_| ()).map_err(|err| err.to_string()))
+                .help("only show commands run at or after this many seconds since the Unix epoch"),
+        )
+        .arg(
+            Arg::with_name("until")
+                .long("until")
+                .takes_value(true)
+                .global(true)
+                .validator(|n| n.parse::<u64>().map(|_| ()).map_err(|err| err.to_string()))
+                .help("only show commands run at or before this many seconds since the Unix epoch"),
+        )
+        .arg(
+            Arg::with_name("count")
+                .long("count")
+                .takes_value(true)
+                .global(true)
+                .validator(|n| n.parse::<usize>().map(|_| ()).map_err(|err| err.to_string()))
+                .help("show at most this many matching entries"),
+        )
+        .arg(
+            Arg::with_name("offset")
+                .long("offset")
+                .takes_value(true)
+                .global(true)
+                .validator(|n| n.parse::<usize>().map(|_| ()).map_err(|err| err.to_string()))
+                .help("skip this many matching entries before showing any"),
+        )
+        .arg(
+            Arg::with_name("builtin")
+                .long("builtin")
+                .global(true)
+                .conflicts_with("no-builtin")
+                .help("only show builtin commands"),
+        )
+        .arg(
+            Arg::with_name("no-builtin")
+                .long("no-builtin")
+                .global(true)
+                .help("exclude builtin commands"),
+        )
+        .arg(
+            Arg::with_name("failed")
+                .long("failed")
+                .global(true)
+                .help("only show commands that finished with a non-zero exit code"),
+        )
         .subcommand(
             SubCommand::with_name("sync")
                 .about("flush the current in-memory history into the history file"),
         )
         .subcommand(SubCommand::with_name("filter").about("filter history to only show the command you want to see")
-            .arg(Arg::with_name("filter-mode").short("m").long("mode").takes_value(true).help("only show commands from the given shell execution mode, if no value is given the current execution mode is used"))
-            .arg(Arg::with_name("filter-base").short("b").long("base").takes_value(true).help("only show commands whose 'base' matches the given base or have no base, if no value is given the current value is used"))
+            .arg(Arg::with_name("filter-mode").short("m").long("mode").takes_value(true).min_values(0).possible_values(&["wrapped", "normal"]).help("only show commands from the given shell execution mode, if no value is given the current execution mode is used"))
+            .arg(Arg::with_name("filter-base").short("b").long("base").takes_value(true).min_values(0).help("only show commands whose 'base' matches the given base or have no base, if no value is given the current value is used"))
+        )
+        .subcommand(
+            SubCommand::with_name("search")
+                .about("interactively search history, Ctrl-R style"),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("write the current history to a file")
+                .arg(Arg::with_name("path").help("the file to write to").required(true))
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .default_value("json")
+                        .possible_values(&["json", "text"])
+                        .help("the on-disk format to write"),
+                )
+                .arg(
+                    Arg::with_name("dedup")
+                        .long("dedup")
+                        .help("collapse consecutive identical commands before writing"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("append the commands in a file to the current history")
+                .arg(Arg::with_name("path").help("the file to read from").required(true))
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .default_value("json")
+                        .possible_values(&["json", "text"])
+                        .help("the on-disk format to read"),
+                ),
         );
 
     let matches = handle_matches!(app, argv);
 
+    let regex = match matches.value_of("regex") {
+        Some(pattern) => match Regex::new(pattern) {
+            Ok(regex) => Some(regex),
+            Err(err) => {
+                write!(err_writer, "Error: invalid regex '{}': {}\n", pattern, err);
+
+                return Err(StatusError { code: 1 });
+            }
+        },
+        None => None,
+    };
+
+    let count: Option<usize> = matches.value_of("count").map(|n| n.parse().unwrap());
+    let offset: usize = matches
+        .value_of("offset")
+        .map(|n| n.parse().unwrap())
+        .unwrap_or(0);
+
+    let since: Option<u64> = matches.value_of("since").map(|n| n.parse().unwrap());
+    let until: Option<u64> = matches.value_of("until").map(|n| n.parse().unwrap());
+
+    let show_builtin = !matches.is_present("no-builtin");
+    let show_non_builtin = !matches.is_present("builtin");
+    let failed_only = matches.is_present("failed");
+
     match matches.subcommand() {
         ("sync", Some(_)) => {
             if let Err(err) = session.history_sync() {
                 write!(err_writer, "Error saving to history file: {}", err);
             }
         }
-        ("filter", Some(sub_matches)) => {
-            let filter_base = sub_matches.is_present("filter-base");
-            let filter_mode = sub_matches.is_present("filter-mode");
+        ("search", Some(_)) => {
+            let _ = reverse_search(out_writer, session);
+        }
+        ("export", Some(sub_matches)) => {
+            let path = PathBuf::from(sub_matches.value_of("path").unwrap());
+            let format = sub_matches.value_of("format").unwrap().parse().unwrap();
+            let dedup = sub_matches.is_present("dedup");
 
-            let entries = session.history_iter().filter(|entry| {
-                if filter_mode && entry.mode != session.mode() {
-                    return false;
-                }
+            if let Err(err) = session.history_export(path.as_path(), format, dedup) {
+                write!(err_writer, "Error exporting history: {}\n", err);
 
-                if entry.base.is_some()
-                    && filter_base
-                    && entry.base.as_ref().unwrap().as_str() != session.base
-                {
-                    return false;
-                }
+                return Err(StatusError { code: 1 });
+            }
+        }
+        ("import", Some(sub_matches)) => {
+            let path = PathBuf::from(sub_matches.value_of("path").unwrap());
+            let format = sub_matches.value_of("format").unwrap().parse().unwrap();
 
-                true
-            });
+            if let Err(err) = session.history_import(path.as_path(), format) {
+                write!(err_writer, "Error importing history: {}\n", err);
 
-            for (i, entry) in entries.enumerate() {
-                write!(out_writer, "{}: {}\n", i, entry.get_command());
+                return Err(StatusError { code: 1 });
             }
         }
+        ("filter", Some(sub_matches)) => {
+            let mode = if sub_matches.is_present("filter-mode") {
+                Some(
+                    sub_matches
+                        .value_of("filter-mode")
+                        .map(|m| m.parse().unwrap())
+                        .unwrap_or_else(|| session.mode()),
+                )
+            } else {
+                None
+            };
+
+            let base = if sub_matches.is_present("filter-base") {
+                Some(
+                    sub_matches
+                        .value_of("filter-base")
+                        .unwrap_or(session.base)
+                        .to_string(),
+                )
+            } else {
+                None
+            };
+
+            print_entries(
+                out_writer,
+                session,
+                mode,
+                base.as_deref(),
+                regex.as_ref(),
+                since,
+                until,
+                show_builtin,
+                show_non_builtin,
+                failed_only,
+                offset,
+                count,
+            );
+        }
         _ => {
-            for (i, entry) in session
-                .history_iter()
-                .filter(|entry| {
-                    entry.is_builtin
-                        || (entry.mode == session.mode()
-                            && (entry.base.is_none()
-                                || entry.base.as_ref().unwrap() == session.base))
-                })
-                .enumerate()
-            {
-                write!(out_writer, "{}: {}\n", i, entry.get_command());
-            }
+            print_entries(
+                out_writer,
+                session,
+                Some(session.mode()),
+                Some(session.base),
+                regex.as_ref(),
+                since,
+                until,
+                show_builtin,
+                show_non_builtin,
+                failed_only,
+                offset,
+                count,
+            );
         }
     }
 
@@ -304,61 +1148,345 @@ mod tests {
     mod test_cd {
         use crate::builtins;
         use crate::error::StatusError;
+        use crate::history::History;
+        use crate::session::{Session, SessionMode};
         use directories::UserDirs;
         use std::env;
+        use std::io::BufWriter;
         use std::path::PathBuf;
 
         #[test]
-        fn test_cd_destination_no_exist() -> Result<(), Box<dyn std::error::Error>> {
-            let expected = Err(StatusError { code: 1 });
-            let actual = builtins::cd(&["cd".to_string(), "no_exist".to_string()]);
+        fn test_cd_destination_no_exist() -> Result<(), Box<dyn std::error::Error>> {
+            let mut err = BufWriter::new(vec![]);
+            let mut session = Session::new(History::empty(), false, "", SessionMode::Wrapped);
+
+            let expected = Err(StatusError { code: 1 });
+            let actual = builtins::cd(
+                &mut err,
+                &mut session,
+                &["cd".to_string(), "no_exist".to_string()],
+            );
+
+            assert_eq!(expected, actual);
+
+            Ok(())
+        }
+
+        #[ignore]
+        #[test]
+        fn test_cd_no_destination() -> Result<(), Box<dyn std::error::Error>> {
+            let old_cwd = env::current_dir()?;
+
+            let mut err = BufWriter::new(vec![]);
+            let mut session = Session::new(History::empty(), false, "", SessionMode::Wrapped);
+
+            let dirs = UserDirs::new().unwrap();
+
+            let expected = ();
+            let expected_cwd = dirs.home_dir();
+
+            let actual = builtins::cd(&mut err, &mut session, &["cd".to_string()])?;
+            let actual_cwd = env::current_dir().unwrap();
+
+            env::set_current_dir(old_cwd)?;
+
+            assert_eq!(expected, actual);
+
+            assert_eq!(expected_cwd, actual_cwd);
+
+            Ok(())
+        }
+
+        #[ignore]
+        #[test]
+        fn test_cd_directory() -> Result<(), Box<dyn std::error::Error>> {
+            let old_cwd = env::current_dir()?;
+
+            let mut err = BufWriter::new(vec![]);
+            let mut session = Session::new(History::empty(), false, "", SessionMode::Wrapped);
+
+            let expected = ();
+            let expected_cwd = PathBuf::from("./tests").canonicalize()?;
+
+            let actual = builtins::cd(
+                &mut err,
+                &mut session,
+                &["cd".to_string(), "tests".to_string()],
+            )?;
+            let actual_cwd = env::current_dir()?;
+
+            env::set_current_dir(old_cwd)?;
+
+            assert_eq!(expected, actual);
+
+            assert_eq!(expected_cwd, actual_cwd);
+
+            Ok(())
+        }
+    }
+
+    mod test_export {
+        use crate::builtins;
+        use std::env;
+        use std::io::BufWriter;
+
+        #[test]
+        fn test_export_sets_var() -> Result<(), Box<dyn std::error::Error>> {
+            let mut out = BufWriter::new(vec![]);
+
+            let expected = Ok(());
+            let actual = builtins::export(
+                &mut out,
+                &["export".to_string(), "WRASH_TEST_EXPORT=1".to_string()],
+            );
+
+            assert_eq!(expected, actual);
+            assert_eq!(Ok("1".to_string()), env::var("WRASH_TEST_EXPORT"));
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_export_bare_unset_var() {
+            let mut out = BufWriter::new(vec![]);
+
+            let expected = Err(crate::error::StatusError { code: 1 });
+            let actual = builtins::export(
+                &mut out,
+                &["export".to_string(), "WRASH_TEST_NOT_SET".to_string()],
+            );
+
+            assert_eq!(expected, actual);
+        }
+    }
+
+    mod test_unset {
+        use crate::builtins;
+        use std::env;
+
+        #[test]
+        fn test_unset_removes_var() {
+            env::set_var("WRASH_TEST_UNSET", "1");
+
+            let expected = Ok(());
+            let actual = builtins::unset(&["unset".to_string(), "WRASH_TEST_UNSET".to_string()]);
+
+            assert_eq!(expected, actual);
+            assert!(env::var("WRASH_TEST_UNSET").is_err());
+        }
+    }
+
+    mod test_alias {
+        use crate::builtins;
+        use crate::history::History;
+        use crate::session::{Session, SessionMode};
+        use std::io::BufWriter;
+
+        #[test]
+        fn test_alias_sets() -> Result<(), Box<dyn std::error::Error>> {
+            let mut out = BufWriter::new(vec![]);
+            let mut session = Session::new(History::empty(), false, "", SessionMode::Wrapped);
+
+            let expected = Ok(());
+            let actual = builtins::alias(
+                &mut out,
+                &mut session,
+                &["alias".to_string(), "ll=ls -l".to_string()],
+            );
+
+            assert_eq!(expected, actual);
+            assert_eq!(Some(&"ls -l".to_string()), session.get_alias("ll"));
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_alias_bare_unknown_name() {
+            let mut out = BufWriter::new(vec![]);
+            let mut session = Session::new(History::empty(), false, "", SessionMode::Wrapped);
+
+            let expected = Err(crate::error::StatusError { code: 1 });
+            let actual = builtins::alias(
+                &mut out,
+                &mut session,
+                &["alias".to_string(), "ll".to_string()],
+            );
+
+            assert_eq!(expected, actual);
+        }
+    }
+
+    mod test_unalias {
+        use crate::builtins;
+        use crate::history::History;
+        use crate::session::{Session, SessionMode};
+
+        #[test]
+        fn test_unalias_removes_alias() {
+            let mut session = Session::new(History::empty(), false, "", SessionMode::Wrapped);
+            session.set_alias("ll".to_string(), "ls -l".to_string());
+
+            let expected = Ok(());
+            let actual = builtins::unalias(&mut session, &["unalias".to_string(), "ll".to_string()]);
+
+            assert_eq!(expected, actual);
+            assert_eq!(None, session.get_alias("ll"));
+        }
+    }
+
+    mod test_source {
+        use crate::builtins;
+        use crate::history::History;
+        use crate::session::{Session, SessionMode};
+        use std::io::{BufWriter, Write};
+        use tempfile::NamedTempFile;
+
+        #[test]
+        fn test_source_runs_lines() -> Result<(), Box<dyn std::error::Error>> {
+            let mut file = NamedTempFile::new()?;
+            writeln!(file, "# a comment")?;
+            writeln!(file, "mode normal")?;
+
+            let mut out = BufWriter::new(vec![]);
+            let mut err = BufWriter::new(vec![]);
+
+            let mut session = Session::new(History::empty(), false, "", SessionMode::Wrapped);
+
+            let expected = Ok(());
+            let actual = builtins::source(
+                &mut out,
+                &mut err,
+                &mut session,
+                &[
+                    "source".to_string(),
+                    file.path().to_string_lossy().to_string(),
+                ],
+            );
+
+            assert_eq!(expected, actual);
+            assert_eq!(SessionMode::Normal, session.mode());
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_source_missing_file() {
+            let mut out = BufWriter::new(vec![]);
+            let mut err = BufWriter::new(vec![]);
+
+            let mut session = Session::new(History::empty(), false, "", SessionMode::Wrapped);
+
+            let expected = Err(crate::error::StatusError { code: 1 });
+            let actual = builtins::source(
+                &mut out,
+                &mut err,
+                &mut session,
+                &["source".to_string(), "/no/such/file".to_string()],
+            );
+
+            assert_eq!(expected, actual);
+        }
+    }
+
+    mod test_jobs {
+        use crate::builtins;
+        use crate::history::History;
+        use crate::session::{Session, SessionMode};
+        use std::io::BufWriter;
+        use std::process::Command;
+        use std::sync::{Arc, Mutex};
+
+        fn spawn_true() -> std::process::Child {
+            Command::new("true").spawn().expect("failed to spawn 'true'")
+        }
+
+        #[test]
+        fn test_jobs_lists_running() -> Result<(), Box<dyn std::error::Error>> {
+            let mut out = BufWriter::new(vec![]);
+            let mut err = BufWriter::new(vec![]);
+
+            let mut session = Session::new(History::empty(), false, "", SessionMode::Wrapped);
+            let id = session
+                .jobs()
+                .insert(spawn_true(), "true".to_string(), Arc::new(Mutex::new(vec![])));
+
+            let expected = Ok(());
+            let actual = builtins::jobs(&mut out, &mut err, &mut session, &["jobs".to_string()]);
 
             assert_eq!(expected, actual);
 
+            let actual_out = String::from_utf8(out.into_inner()?)?;
+            assert_eq!(format!("[{}] Running\ttrue\n", id), actual_out);
+
             Ok(())
         }
 
-        #[ignore]
         #[test]
-        fn test_cd_no_destination() -> Result<(), Box<dyn std::error::Error>> {
-            let old_cwd = env::current_dir()?;
-
-            let dirs = UserDirs::new().unwrap();
-
-            let expected = ();
-            let expected_cwd = dirs.home_dir();
+        fn test_jobs_output() -> Result<(), Box<dyn std::error::Error>> {
+            let mut out = BufWriter::new(vec![]);
+            let mut err = BufWriter::new(vec![]);
 
-            let actual = builtins::cd(&["cd".to_string()])?;
-            let actual_cwd = env::current_dir().unwrap();
+            let mut session = Session::new(History::empty(), false, "", SessionMode::Wrapped);
+            let output = Arc::new(Mutex::new(b"hello\n".to_vec()));
+            let id = session.jobs().insert(spawn_true(), "true".to_string(), output);
 
-            env::set_current_dir(old_cwd)?;
+            let expected = Ok(());
+            let actual = builtins::jobs(
+                &mut out,
+                &mut err,
+                &mut session,
+                &["jobs".to_string(), "output".to_string(), id.to_string()],
+            );
 
             assert_eq!(expected, actual);
 
-            assert_eq!(expected_cwd, actual_cwd);
+            let actual_out = String::from_utf8(out.into_inner()?)?;
+            assert_eq!("hello\n", actual_out);
 
             Ok(())
         }
 
-        #[ignore]
         #[test]
-        fn test_cd_directory() -> Result<(), Box<dyn std::error::Error>> {
-            let old_cwd = env::current_dir()?;
-
-            let expected = ();
-            let expected_cwd = PathBuf::from("./tests").canonicalize()?;
+        fn test_fg_waits_and_reaps() -> Result<(), Box<dyn std::error::Error>> {
+            let mut out = BufWriter::new(vec![]);
+            let mut err = BufWriter::new(vec![]);
 
-            let actual = builtins::cd(&["cd".to_string(), "tests".to_string()])?;
-            let actual_cwd = env::current_dir()?;
+            let mut session = Session::new(History::empty(), false, "", SessionMode::Wrapped);
+            let id = session
+                .jobs()
+                .insert(spawn_true(), "true".to_string(), Arc::new(Mutex::new(vec![])));
 
-            env::set_current_dir(old_cwd)?;
+            let expected = Ok(());
+            let actual = builtins::fg(
+                &mut out,
+                &mut err,
+                &mut session,
+                &["fg".to_string(), id.to_string()],
+            );
 
             assert_eq!(expected, actual);
-
-            assert_eq!(expected_cwd, actual_cwd);
+            assert!(session.jobs().get_mut(id).is_none());
 
             Ok(())
         }
+
+        #[test]
+        fn test_fg_no_such_job() {
+            let mut out = BufWriter::new(vec![]);
+            let mut err = BufWriter::new(vec![]);
+
+            let mut session = Session::new(History::empty(), false, "", SessionMode::Wrapped);
+
+            let expected = Err(crate::error::StatusError { code: 1 });
+            let actual = builtins::fg(
+                &mut out,
+                &mut err,
+                &mut session,
+                &["fg".to_string(), "1".to_string()],
+            );
+
+            assert_eq!(expected, actual);
+        }
     }
 
     // todo: test output to stdout
@@ -551,6 +1679,7 @@ mod tests {
         use crate::history::{History, HistoryEntry};
         use crate::session::{Session, SessionMode};
         use std::io::BufWriter;
+        use tempfile::NamedTempFile;
 
         fn get_history() -> History {
             let mut history = History::empty();
@@ -560,6 +1689,9 @@ mod tests {
                 Some("git".to_string()),
                 SessionMode::Wrapped,
                 false,
+                1_000,
+                0,
+                0,
             ));
 
             history.push(HistoryEntry::new(
@@ -567,6 +1699,9 @@ mod tests {
                 None,
                 SessionMode::Wrapped,
                 true,
+                2_000,
+                0,
+                0,
             ));
 
             history.push(HistoryEntry::new(
@@ -574,6 +1709,9 @@ mod tests {
                 None,
                 SessionMode::Normal,
                 false,
+                3_000,
+                0,
+                0,
             ));
 
             history.push(HistoryEntry::new(
@@ -581,6 +1719,9 @@ mod tests {
                 None,
                 SessionMode::Normal,
                 true,
+                4_000,
+                0,
+                0,
             ));
 
             history.push(HistoryEntry::new(
@@ -588,6 +1729,9 @@ mod tests {
                 Some("cargo".to_string()),
                 SessionMode::Wrapped,
                 false,
+                5_000,
+                0,
+                0,
             ));
 
             history
@@ -714,7 +1858,8 @@ mod tests {
 
             assert_eq!(expected, actual);
 
-            let expected_out = String::from("0: git add -A\n1: cargo clippy\n");
+            let expected_out =
+                String::from("0: git add -A\n1: mode normal\n2: mode wrapped\n3: cargo clippy\n");
             let actual_out = String::from_utf8(out.into_inner()?).unwrap();
 
             assert_eq!(expected_out, actual_out);
@@ -792,7 +1937,9 @@ mod tests {
 
             assert_eq!(expected, actual);
 
-            let expected_out = String::from("0: mode normal\n1: git commit -m 'some commit message'\n");
+            let expected_out = String::from(
+                "0: git add -A\n1: mode normal\n2: git commit -m 'some commit message'\n3: mode wrapped\n",
+            );
             let actual_out = String::from_utf8(out.into_inner()?).unwrap();
 
             assert_eq!(expected_out, actual_out);
@@ -804,5 +1951,232 @@ mod tests {
 
             Ok(())
         }
+
+        #[test]
+        fn test_history_filter_regex() -> Result<(), Box<dyn std::error::Error>> {
+            let mut out = BufWriter::new(vec![]);
+            let mut err = BufWriter::new(vec![]);
+
+            let history = get_history();
+
+            let mut session = Session::new(history, false, "", SessionMode::Wrapped);
+
+            let expected = Ok(());
+            let actual = builtins::history(
+                &mut out,
+                &mut err,
+                &mut session,
+                &[
+                    "history".to_string(),
+                    "--regex".to_string(),
+                    "^git".to_string(),
+                    "filter".to_string(),
+                ],
+            );
+
+            assert_eq!(expected, actual);
+
+            let expected_out =
+                String::from("0: git add -A\n1: git commit -m 'some commit message'\n");
+            let actual_out = String::from_utf8(out.into_inner()?).unwrap();
+
+            assert_eq!(expected_out, actual_out);
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_history_count_and_offset() -> Result<(), Box<dyn std::error::Error>> {
+            let mut out = BufWriter::new(vec![]);
+            let mut err = BufWriter::new(vec![]);
+
+            let history = get_history();
+
+            let mut session = Session::new(history, false, "", SessionMode::Wrapped);
+
+            let expected = Ok(());
+            let actual = builtins::history(
+                &mut out,
+                &mut err,
+                &mut session,
+                &[
+                    "history".to_string(),
+                    "--offset".to_string(),
+                    "1".to_string(),
+                    "--count".to_string(),
+                    "2".to_string(),
+                    "filter".to_string(),
+                ],
+            );
+
+            assert_eq!(expected, actual);
+
+            let expected_out =
+                String::from("1: mode normal\n2: git commit -m 'some commit message'\n");
+            let actual_out = String::from_utf8(out.into_inner()?).unwrap();
+
+            assert_eq!(expected_out, actual_out);
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_history_no_builtin() -> Result<(), Box<dyn std::error::Error>> {
+            let mut out = BufWriter::new(vec![]);
+            let mut err = BufWriter::new(vec![]);
+
+            let history = get_history();
+
+            let mut session = Session::new(history, false, "", SessionMode::Wrapped);
+
+            let expected = Ok(());
+            let actual = builtins::history(
+                &mut out,
+                &mut err,
+                &mut session,
+                &[
+                    "history".to_string(),
+                    "--no-builtin".to_string(),
+                    "filter".to_string(),
+                ],
+            );
+
+            assert_eq!(expected, actual);
+
+            let expected_out = String::from(
+                "0: git add -A\n1: git commit -m 'some commit message'\n2: cargo clippy\n",
+            );
+            let actual_out = String::from_utf8(out.into_inner()?).unwrap();
+
+            assert_eq!(expected_out, actual_out);
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_history_since_until() -> Result<(), Box<dyn std::error::Error>> {
+            let mut out = BufWriter::new(vec![]);
+            let mut err = BufWriter::new(vec![]);
+
+            let history = get_history();
+
+            let mut session = Session::new(history, false, "", SessionMode::Wrapped);
+
+            let expected = Ok(());
+            let actual = builtins::history(
+                &mut out,
+                &mut err,
+                &mut session,
+                &[
+                    "history".to_string(),
+                    "--since".to_string(),
+                    "2000".to_string(),
+                    "--until".to_string(),
+                    "4000".to_string(),
+                    "filter".to_string(),
+                ],
+            );
+
+            assert_eq!(expected, actual);
+
+            let expected_out = String::from(
+                "0: mode normal\n1: git commit -m 'some commit message'\n2: mode wrapped\n",
+            );
+            let actual_out = String::from_utf8(out.into_inner()?).unwrap();
+
+            assert_eq!(expected_out, actual_out);
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_history_since_rejects_non_numeric_value() {
+            let mut out = BufWriter::new(vec![]);
+            let mut err = BufWriter::new(vec![]);
+
+            let history = get_history();
+
+            let mut session = Session::new(history, false, "", SessionMode::Wrapped);
+
+            let expected = Err(StatusError { code: 1 });
+            let actual = builtins::history(
+                &mut out,
+                &mut err,
+                &mut session,
+                &["history".to_string(), "--since".to_string(), "abc".to_string()],
+            );
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_history_until_rejects_non_numeric_value() {
+            let mut out = BufWriter::new(vec![]);
+            let mut err = BufWriter::new(vec![]);
+
+            let history = get_history();
+
+            let mut session = Session::new(history, false, "", SessionMode::Wrapped);
+
+            let expected = Err(StatusError { code: 1 });
+            let actual = builtins::history(
+                &mut out,
+                &mut err,
+                &mut session,
+                &["history".to_string(), "--until".to_string(), "abc".to_string()],
+            );
+
+            assert_eq!(expected, actual);
+        }
+
+        #[test]
+        fn test_history_export_import_json() -> Result<(), Box<dyn std::error::Error>> {
+            let mut out = BufWriter::new(vec![]);
+            let mut err = BufWriter::new(vec![]);
+
+            let history = get_history();
+            let mut session = Session::new(history, false, "", SessionMode::Wrapped);
+
+            let file = NamedTempFile::new()?;
+            let path = file.path().to_string_lossy().to_string();
+
+            let expected = Ok(());
+            let actual = builtins::history(
+                &mut out,
+                &mut err,
+                &mut session,
+                &["history".to_string(), "export".to_string(), path.clone()],
+            );
+
+            assert_eq!(expected, actual);
+
+            let mut session = Session::new(History::empty(), false, "", SessionMode::Wrapped);
+
+            let actual = builtins::history(
+                &mut out,
+                &mut err,
+                &mut session,
+                &["history".to_string(), "import".to_string(), path],
+            );
+
+            assert_eq!(expected, actual);
+
+            let mut out = BufWriter::new(vec![]);
+            builtins::history(
+                &mut out,
+                &mut err,
+                &mut session,
+                &["history".to_string(), "--no-builtin".to_string(), "filter".to_string()],
+            )?;
+
+            let expected_out = String::from(
+                "0: git add -A\n1: git commit -m 'some commit message'\n2: cargo clippy\n",
+            );
+            let actual_out = String::from_utf8(out.into_inner()?).unwrap();
+
+            assert_eq!(expected_out, actual_out);
+
+            Ok(())
+        }
     }
 }
@@ -1,9 +1,107 @@
 use faccess::PathExt;
+use std::collections::HashSet;
+use std::env;
+use std::fmt::{self, Display, Formatter};
 use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
 
 use glob::{self, PatternError};
 
-// todo: handle duplicates
+use crate::session::CompletionMatchMode;
+
+/// A single completion filter pattern, parsed from its typed-prefix string
+/// form.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// `path:<glob>` - matches the full path against a glob.
+    Path(glob::Pattern),
+
+    /// `rootfilesin:<dir>` - matches only the immediate (non-recursive)
+    /// files of `dir`.
+    RootFilesIn(PathBuf),
+}
+
+impl Pattern {
+    fn matches(&self, path: &Path) -> bool {
+        match self {
+            Pattern::Path(pattern) => pattern.matches_path(path),
+            Pattern::RootFilesIn(dir) => path.is_file() && path.parent() == Some(dir.as_path()),
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = PatternParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(glob) = s.strip_prefix("path:") {
+            Ok(Pattern::Path(glob::Pattern::new(glob)?))
+        } else if let Some(dir) = s.strip_prefix("rootfilesin:") {
+            Ok(Pattern::RootFilesIn(PathBuf::from(dir)))
+        } else {
+            Err(PatternParseError::UnrecognizedPrefix(s.to_string()))
+        }
+    }
+}
+
+/// Error parsing a [Pattern] from its typed-prefix string form.
+#[derive(Debug)]
+pub enum PatternParseError {
+    Glob(PatternError),
+    UnrecognizedPrefix(String),
+}
+
+impl Display for PatternParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PatternParseError::Glob(err) => write!(f, "{}", err),
+            PatternParseError::UnrecognizedPrefix(s) => write!(
+                f,
+                "unrecognized completion pattern prefix in '{}', expected 'path:' or 'rootfilesin:'",
+                s
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PatternParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PatternParseError::Glob(err) => Some(err),
+            PatternParseError::UnrecognizedPrefix(_) => None,
+        }
+    }
+}
+
+impl From<PatternError> for PatternParseError {
+    fn from(err: PatternError) -> Self {
+        PatternParseError::Glob(err)
+    }
+}
+
+/// An ordered allow/deny filter for completion results, composed so `deny`
+/// subtracts from `allow` (a difference, not a first-match-wins list). An
+/// empty allow list means "allow everything" rather than "allow nothing",
+/// so completion isn't filtered out entirely until the user actually
+/// configures a pattern.
+#[derive(Debug, Clone, Default)]
+pub struct Matchers {
+    allow: Vec<Pattern>,
+    deny: Vec<Pattern>,
+}
+
+impl Matchers {
+    pub fn new(allow: Vec<Pattern>, deny: Vec<Pattern>) -> Matchers {
+        Matchers { allow, deny }
+    }
+
+    pub fn is_match(&self, path: &Path) -> bool {
+        let allowed = self.allow.is_empty() || self.allow.iter().any(|pattern| pattern.matches(path));
+        let denied = self.deny.iter().any(|pattern| pattern.matches(path));
+
+        allowed && !denied
+    }
+}
 
 /// Merge the prefix path with the completion path to restore any path
 /// component lost during processing.
@@ -19,40 +117,158 @@ fn merge_prefix_with_completion(original_path: &Path, new: &Path) -> Option<Path
     None
 }
 
+/// Rewrite `path` relative to `cwd` when possible, matching how an
+/// interactive shell's own completion behaves. Left unchanged if `path`
+/// isn't under `cwd`.
+fn relative_to_cwd(path: PathBuf, cwd: &Path) -> PathBuf {
+    path.strip_prefix(cwd).map(Path::to_path_buf).unwrap_or(path)
+}
+
+/// Score how well `pattern`'s characters appear, in order, as a
+/// subsequence of `candidate`, case-insensitively. Returns `None` if
+/// `pattern` isn't a subsequence of `candidate` at all. A match that
+/// stays contiguous, or that lands right after a `_`/`-`/`.` separator
+/// (or at the very start of `candidate`), scores higher than one
+/// scattered through unrelated characters, so e.g. `afw` ranks
+/// `a_file_as_well` above `another_file`.
+fn fuzzy_score(candidate: &str, pattern: &str) -> Option<i32> {
+    let candidate_chars: Vec<char> = candidate.chars().collect();
+
+    let mut score = 0;
+    let mut search_from = 0;
+    let mut previous_match: Option<usize> = None;
+
+    for pattern_char in pattern.chars() {
+        let lower = pattern_char.to_ascii_lowercase();
+        let found_offset = candidate_chars[search_from..]
+            .iter()
+            .position(|c| c.to_ascii_lowercase() == lower)?;
+        let found = search_from + found_offset;
+
+        score += 1;
+        if previous_match == Some(found.wrapping_sub(1)) {
+            score += 2;
+        }
+        if found == 0 || matches!(candidate_chars[found - 1], '_' | '-' | '.') {
+            score += 3;
+        }
+
+        previous_match = Some(found);
+        search_from = found + 1;
+    }
+
+    Some(score)
+}
+
+/// Whether `name` matches `pattern` under `mode`; see
+/// [CompletionMatchMode]. Always `true` for [CompletionMatchMode::ExactPrefix]
+/// since that case is already handled by the glob pattern passed to
+/// [search_prefix].
+fn matches_mode(name: &str, pattern: &str, mode: CompletionMatchMode) -> bool {
+    match mode {
+        CompletionMatchMode::ExactPrefix => true,
+        CompletionMatchMode::CaseInsensitive => {
+            name.to_lowercase().starts_with(&pattern.to_lowercase())
+        }
+        CompletionMatchMode::Fuzzy => fuzzy_score(name, pattern).is_some(),
+    }
+}
+
 /// Search the file system for paths with a given prefix allowing for wildcards. The returns
-/// `pathBuf`s are normalized (meaning any `.` and `..` are stripped out.
+/// `pathBuf`s are normalized (meaning any `.` and `..` are stripped out, and are emitted relative
+/// to `cwd` where possible.
+///
+/// `matchers` filters the results; see [Matchers]. `mode` controls how `prefix`'s file name is
+/// matched against candidates; see [CompletionMatchMode]. Under
+/// [CompletionMatchMode::CaseInsensitive] or [CompletionMatchMode::Fuzzy], every entry of
+/// `prefix`'s parent directory is considered and results are ranked so the best matches (under
+/// [CompletionMatchMode::Fuzzy]) come first; [CompletionMatchMode::ExactPrefix] keeps relying on
+/// the glob pattern itself, as before.
 ///
 /// If any error is encountered while reading a file, that file is ignored.
-pub fn search_prefix(prefix: &Path) -> Result<impl Iterator<Item = PathBuf>, PatternError> {
-    let prefix_path = PathBuf::from(format!("{}*", prefix.to_str().unwrap()));
+pub fn search_prefix(
+    prefix: &Path,
+    cwd: &Path,
+    matchers: &Matchers,
+    mode: CompletionMatchMode,
+) -> Result<impl Iterator<Item = PathBuf>, PatternError> {
+    let file_name = prefix
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let glob_path = match mode {
+        CompletionMatchMode::ExactPrefix => PathBuf::from(format!("{}*", prefix.to_str().unwrap())),
+        CompletionMatchMode::CaseInsensitive | CompletionMatchMode::Fuzzy => {
+            match prefix.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                Some(parent) => parent.join("*"),
+                None => PathBuf::from("*"),
+            }
+        }
+    };
+
+    let matchers = matchers.clone();
+    let cwd = cwd.to_path_buf();
 
-    Ok(glob::glob(prefix_path.to_str().unwrap())?.
-        filter_map(Result::ok)
-        .map(move |p| if let Some(merged) = merge_prefix_with_completion(prefix_path.as_path(), p.as_path()) {
+    let mut found: Vec<PathBuf> = glob::glob(glob_path.to_str().unwrap())?
+        .filter_map(Result::ok)
+        .map(|p| if let Some(merged) = merge_prefix_with_completion(glob_path.as_path(), p.as_path()) {
             merged
         } else {
             p
-        }))
+        })
+        .filter(|path| matchers.is_match(path))
+        .filter(|path| {
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            matches_mode(&name, &file_name, mode)
+        })
+        .collect();
+
+    if mode == CompletionMatchMode::Fuzzy {
+        found.sort_by_key(|path| {
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            std::cmp::Reverse(fuzzy_score(&name, &file_name).unwrap_or(0))
+        });
+    }
+
+    Ok(found.into_iter().map(move |path| relative_to_cwd(path, &cwd)))
 }
 
 /// Searches the directories on the system's PATH environment variable for
 /// paths with the given prefix. The returned paths are stripped of any parent
-/// directories.
+/// directories and deduplicated, so an executable name found under two
+/// different PATH entries is only returned once (the first one found wins).
+///
+/// `matchers` filters the results; see [Matchers]. `mode` controls how `prefix`'s file name is
+/// matched against candidates in each PATH directory; see [CompletionMatchMode].
 ///
 /// If any error is encountered while reading a file, that file is ignored.
 pub fn search_path<'a>(
     prefix: &'a Path,
+    cwd: &'a Path,
     path_val: &'a str,
+    matchers: &Matchers,
+    mode: CompletionMatchMode,
 ) -> Result<impl Iterator<Item = PathBuf> + 'a, PatternError> {
+    let mut seen = HashSet::new();
+    let matchers = matchers.clone();
+
     let globs = path_val
         .split(':')
         .map(move |dir: &str| {
             let full_prefix = Path::new(dir).join(prefix);
 
             // todo: handle pattern errors
-            let found = search_prefix(full_prefix.as_path())
+            let found = search_prefix(full_prefix.as_path(), cwd, &matchers, mode)
                 .unwrap()
-                .into_iter()
                 .filter(|path| !path.is_dir() && path.executable())
                 .map(|path| match path.components().last() {
                     Some(component) => PathBuf::from(component.as_os_str()),
@@ -61,7 +277,8 @@ pub fn search_path<'a>(
 
             found
         })
-        .flatten();
+        .flatten()
+        .filter(move |path| seen.insert(path.clone()));
 
     Ok(globs)
 }
@@ -70,7 +287,10 @@ pub fn search_path<'a>(
 mod test {
     use std::{env, path};
     use crate::completion;
+    use crate::completion::{Matchers, Pattern};
+    use crate::session::CompletionMatchMode;
     use std::path::{Path, PathBuf};
+    use std::str::FromStr;
 
     fn get_resource_path(components: &[&str]) -> PathBuf {
         vec!["tests", "resources"].iter().chain(components.iter()).collect()
@@ -89,7 +309,7 @@ mod test {
         let dir_path = push_trailing_slash(get_resource_path(&["a_directory"]));
 
         let mut actual =
-            completion::search_prefix(dir_path.as_path())?;
+            completion::search_prefix(dir_path.as_path(), Path::new("."), &Matchers::default(), CompletionMatchMode::ExactPrefix)?;
 
         assert_eq!(
             Some(get_resource_path(&["a_directory", "a_file"])),
@@ -116,7 +336,7 @@ mod test {
     fn test_search_dir_with_common_prefix() -> Result<(), Box<dyn std::error::Error>> {
         let dir_path = get_resource_path(&["a_directory"]);
 
-        let mut actual = completion::search_prefix(dir_path.join("a").as_path())?;
+        let mut actual = completion::search_prefix(dir_path.join("a").as_path(), Path::new("."), &Matchers::default(), CompletionMatchMode::ExactPrefix)?;
 
         assert_eq!(
             Some(get_resource_path(&["a_directory", "a_file"])),
@@ -135,7 +355,7 @@ mod test {
     fn test_search_dir_with_directory() -> Result<(), Box<dyn std::error::Error>> {
         let prefix_path = get_resource_path(&["a_directory", "directory", "a"]);
 
-        let mut actual = completion::search_prefix(prefix_path.as_path())?;
+        let mut actual = completion::search_prefix(prefix_path.as_path(), Path::new("."), &Matchers::default(), CompletionMatchMode::ExactPrefix)?;
 
         assert_eq!(Some(get_resource_path(&["a_directory", "directory", "a_child"])), actual.next());
         assert_eq!(None, actual.next());
@@ -143,6 +363,45 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_search_dir_with_allow_pattern() -> Result<(), Box<dyn std::error::Error>> {
+        let dir_path = get_resource_path(&["a_directory"]);
+        let allow = vec![Pattern::from_str("path:*/a_file")?];
+
+        let mut actual = completion::search_prefix(
+            dir_path.join("").as_path(),
+            Path::new("."),
+            &Matchers::new(allow, Vec::new()),
+            CompletionMatchMode::ExactPrefix,
+        )?;
+
+        assert_eq!(
+            Some(get_resource_path(&["a_directory", "a_file"])),
+            actual.next()
+        );
+        assert_eq!(None, actual.next());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_dir_with_deny_pattern() -> Result<(), Box<dyn std::error::Error>> {
+        let dir_path = push_trailing_slash(get_resource_path(&["a_directory"]));
+        let deny = vec![Pattern::RootFilesIn(get_resource_path(&["a_directory"]))];
+
+        let mut actual =
+            completion::search_prefix(dir_path.as_path(), Path::new("."), &Matchers::new(Vec::new(), deny), CompletionMatchMode::ExactPrefix)?;
+
+        // every root file in a_directory is denied, leaving only the directory
+        assert_eq!(
+            Some(get_resource_path(&["a_directory", "directory"])),
+            actual.next()
+        );
+        assert_eq!(None, actual.next());
+
+        Ok(())
+    }
+
     #[test]
     fn test_on_path() -> Result<(), Box<dyn std::error::Error>> {
         let new_path = vec![
@@ -154,7 +413,7 @@ mod test {
         .collect::<Vec<&str>>()
         .join(":");
 
-        let mut actual = completion::search_path(Path::new("a"), new_path.as_str())?;
+        let mut actual = completion::search_path(Path::new("a"), Path::new("."), new_path.as_str(), &Matchers::default(), CompletionMatchMode::ExactPrefix)?;
 
         assert_eq!(Some(PathBuf::from("a_file")), actual.next());
         assert_eq!(Some(PathBuf::from("a_final_file")), actual.next());
@@ -163,6 +422,46 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_on_path_deduplicates() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = get_resource_path(&["a_directory"]);
+        let new_path = vec![dir.clone(), dir]
+            .iter()
+            .map(|entry| entry.to_str().unwrap())
+            .collect::<Vec<&str>>()
+            .join(":");
+
+        let actual: Vec<PathBuf> =
+            completion::search_path(Path::new("a"), Path::new("."), new_path.as_str(), &Matchers::default(), CompletionMatchMode::ExactPrefix)?.collect();
+
+        assert_eq!(vec![PathBuf::from("a_file")], actual);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pattern_from_str_unrecognized_prefix() {
+        assert!(Pattern::from_str("nope:whatever").is_err());
+    }
+
+    #[test]
+    fn test_fuzzy_score_matches_subsequence() {
+        assert!(completion::fuzzy_score("a_file_as_well", "afw").is_some());
+    }
+
+    #[test]
+    fn test_fuzzy_score_rejects_non_subsequence() {
+        assert_eq!(None, completion::fuzzy_score("a_file", "zzz"));
+    }
+
+    #[test]
+    fn test_fuzzy_score_ranks_word_start_matches_higher() {
+        let word_starts = completion::fuzzy_score("a_file_was_here", "afw").unwrap();
+        let scattered = completion::fuzzy_score("xxafwxx", "afw").unwrap();
+
+        assert!(word_starts > scattered);
+    }
+
     #[ignore]
     #[test]
     fn test_search_dir_for_executable_in_cwd() -> Result<(), Box<dyn std::error::Error>> {
@@ -170,7 +469,7 @@ mod test {
         let new_cwd = get_resource_path(&["a_directory", "directory"]);
 
         env::set_current_dir(new_cwd)?;
-        let mut actual = completion::search_prefix(Path::new(".").join("a").as_path())?;
+        let mut actual = completion::search_prefix(Path::new(".").join("a").as_path(), Path::new("."), &Matchers::default(), CompletionMatchMode::ExactPrefix)?;
 
         assert_eq!(Some(Path::new(".").join("a_child")), actual.next());
         assert_eq!(None, actual.next());
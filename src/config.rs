@@ -0,0 +1,324 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::completion::{Matchers, Pattern};
+use crate::session::SessionMode;
+use crate::WrashError;
+
+/// A layered config loaded from `$XDG_CONFIG_HOME/wrash/config`, used to
+/// supply defaults for things `wrapped_main` would otherwise have to
+/// hardcode: the prompt format string, the default [SessionMode], whether
+/// `is_frozen` is forced, and history rotation limits.
+///
+/// Config files are `[section]` headers followed by `key = value` items.
+/// Leading/trailing whitespace around keys and values is trimmed, lines
+/// starting with whitespace continue the previous value (joined with a
+/// single space), and blank lines or lines starting with `#`/`;` are
+/// comments. Two directives are also recognized: `%include <path>` merges
+/// another config file in at that point, and `%unset <key>` removes a
+/// previously-set key so an earlier layer's value doesn't leak through.
+/// Later values always win, whether from a later key in the same file or a
+/// later `%include`.
+pub struct Config {
+    sections: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Config {
+    pub fn empty() -> Config {
+        Config {
+            sections: BTreeMap::new(),
+        }
+    }
+
+    /// Load `$XDG_CONFIG_HOME/wrash/config`, returning an empty config if it
+    /// (or the directory it would live in) doesn't exist.
+    pub fn load() -> Result<Config, WrashError> {
+        match Config::find_config_file() {
+            Some(path) if path.is_file() => {
+                let mut config = Config::empty();
+                config.merge_file(&path)?;
+                Ok(config)
+            }
+            _ => Ok(Config::empty()),
+        }
+    }
+
+    fn find_config_file() -> Option<PathBuf> {
+        directories::ProjectDirs::from("", "", "wrash").map(|dirs| dirs.config_dir().join("config"))
+    }
+
+    /// Parse `path` and merge it into `self`, later values overriding
+    /// earlier ones already present. `%include` directives call back into
+    /// this method, so a nested include is merged inline at the point it
+    /// appears rather than after the rest of the including file.
+    fn merge_file(&mut self, path: &Path) -> Result<(), WrashError> {
+        let contents = fs::read_to_string(path)?;
+
+        let mut section = String::new();
+        let mut pending: Option<(String, String)> = None;
+
+        for raw_line in contents.lines() {
+            let is_continuation = pending.is_some()
+                && raw_line.starts_with(|c: char| c == ' ' || c == '\t')
+                && !raw_line.trim().is_empty();
+
+            if is_continuation {
+                if let Some((_, value)) = pending.as_mut() {
+                    value.push(' ');
+                    value.push_str(raw_line.trim());
+                }
+
+                continue;
+            }
+
+            if let Some((key, value)) = pending.take() {
+                self.set(&section, key, value);
+            }
+
+            let line = raw_line.trim();
+
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].trim().to_string();
+                continue;
+            }
+
+            if let Some(rest) = line.strip_prefix("%include") {
+                let include_path = resolve_include(path, rest.trim());
+                self.merge_file(&include_path)?;
+                continue;
+            }
+
+            if let Some(rest) = line.strip_prefix("%unset") {
+                self.unset(&section, rest.trim());
+                continue;
+            }
+
+            if let Some(eq) = line.find('=') {
+                let key = line[..eq].trim().to_string();
+                let value = line[eq + 1..].trim().to_string();
+
+                pending = Some((key, value));
+            }
+        }
+
+        if let Some((key, value)) = pending.take() {
+            self.set(&section, key, value);
+        }
+
+        Ok(())
+    }
+
+    fn set(&mut self, section: &str, key: String, value: String) {
+        self.sections
+            .entry(section.to_string())
+            .or_insert_with(BTreeMap::new)
+            .insert(key, value);
+    }
+
+    fn unset(&mut self, section: &str, key: &str) {
+        if let Some(section) = self.sections.get_mut(section) {
+            section.remove(key);
+        }
+    }
+
+    /// Look up `key` within `section`.
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.sections.get(section)?.get(key).map(String::as_str)
+    }
+
+    /// The `format` key of the `[prompt]` section, used in place of
+    /// [crate::DEFAULT_PROMPT_FORMAT].
+    pub fn prompt_format(&self) -> Option<&str> {
+        self.get("prompt", "format")
+    }
+
+    /// The `mode` key of the `[session]` section, parsed as a [SessionMode].
+    pub fn default_mode(&self) -> Option<SessionMode> {
+        self.get("session", "mode")?.parse().ok()
+    }
+
+    /// The `frozen` key of the `[session]` section.
+    pub fn is_frozen(&self) -> Option<bool> {
+        self.get("session", "frozen")?.parse().ok()
+    }
+
+    /// The `max_size` key of the `[history]` section, in bytes.
+    pub fn history_max_size(&self) -> Option<u64> {
+        self.get("history", "max_size")?.parse().ok()
+    }
+
+    /// The `max_files` key of the `[history]` section.
+    pub fn history_max_files(&self) -> Option<u32> {
+        self.get("history", "max_files")?.parse().ok()
+    }
+
+    /// The completion [Matchers] configured for the wrapped `command` under
+    /// `[completion.<command>]`'s comma-separated `allow` and `deny` keys.
+    /// Patterns that fail to parse are silently dropped rather than failing
+    /// completion outright.
+    pub fn completion_matchers(&self, command: &str) -> Matchers {
+        let section = format!("completion.{}", command);
+
+        let allow = self
+            .get(&section, "allow")
+            .map(parse_patterns)
+            .unwrap_or_default();
+        let deny = self
+            .get(&section, "deny")
+            .map(parse_patterns)
+            .unwrap_or_default();
+
+        Matchers::new(allow, deny)
+    }
+
+    /// The `dynamic` key of `[completion.<command>]`, controlling whether
+    /// `command`'s own clap dynamic completion protocol is used in place of
+    /// filesystem completion; see [crate::session::Session::set_dynamic_completion_enabled].
+    /// Defaults to `false` since not every wrapped binary supports it.
+    pub fn dynamic_completion(&self, command: &str) -> bool {
+        let section = format!("completion.{}", command);
+
+        self.get(&section, "dynamic")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(false)
+    }
+}
+
+fn parse_patterns(value: &str) -> Vec<Pattern> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| Pattern::from_str(s).ok())
+        .collect()
+}
+
+/// Resolve an `%include` path relative to the directory of the file that
+/// references it, unless `include` is already absolute.
+fn resolve_include(including_file: &Path, include: &str) -> PathBuf {
+    let include_path = PathBuf::from(include);
+
+    if include_path.is_absolute() {
+        include_path
+    } else {
+        including_file
+            .parent()
+            .map(|dir| dir.join(&include_path))
+            .unwrap_or(include_path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::Config;
+    use crate::session::SessionMode;
+
+    fn parse(source: &str) -> Config {
+        let mut config = Config::empty();
+        let file = tempfile::NamedTempFile::new().unwrap();
+
+        std::fs::write(file.path(), source).unwrap();
+        config.merge_file(file.path()).unwrap();
+
+        config
+    }
+
+    #[test]
+    fn test_simple_key_value() {
+        let config = parse("[prompt]\nformat = %u $ \n");
+
+        assert_eq!(Some("%u $"), config.get("prompt", "format"));
+    }
+
+    #[test]
+    fn test_trailing_whitespace_is_trimmed() {
+        let config = parse("[session]\nmode = wrapped   \n");
+
+        assert_eq!(Some(SessionMode::Wrapped), config.default_mode());
+    }
+
+    #[test]
+    fn test_comments_and_blank_lines_are_skipped() {
+        let config = parse("# a comment\n\n; another comment\n[session]\nmode = normal\n");
+
+        assert_eq!(Some(SessionMode::Normal), config.default_mode());
+    }
+
+    #[test]
+    fn test_continuation_line_is_appended() {
+        let config = parse("[prompt]\nformat = %u\n  %g $ \n");
+
+        assert_eq!(Some("%u %g $"), config.get("prompt", "format"));
+    }
+
+    #[test]
+    fn test_unset_removes_earlier_key() {
+        let config = parse("[session]\nmode = wrapped\n%unset mode\n");
+
+        assert_eq!(None, config.default_mode());
+    }
+
+    #[test]
+    fn test_later_value_wins() {
+        let config = parse("[session]\nmode = wrapped\nmode = normal\n");
+
+        assert_eq!(Some(SessionMode::Normal), config.default_mode());
+    }
+
+    #[test]
+    fn test_completion_matchers_allow_and_deny() {
+        let config = parse(
+            "[completion.git]\nallow = path:src/*.rs, rootfilesin:src\ndeny = path:src/*_test.rs\n",
+        );
+
+        let matchers = config.completion_matchers("git");
+
+        assert!(matchers.is_match(std::path::Path::new("src/lib.rs")));
+        assert!(!matchers.is_match(std::path::Path::new("src/lib_test.rs")));
+        assert!(!matchers.is_match(std::path::Path::new("src/lib.txt")));
+    }
+
+    #[test]
+    fn test_completion_matchers_defaults_to_allow_all() {
+        let config = parse("[completion.git]\n");
+
+        let matchers = config.completion_matchers("git");
+
+        assert!(matchers.is_match(std::path::Path::new("anything")));
+    }
+
+    #[test]
+    fn test_dynamic_completion_defaults_to_false() {
+        let config = parse("[completion.git]\n");
+
+        assert!(!config.dynamic_completion("git"));
+    }
+
+    #[test]
+    fn test_dynamic_completion_enabled() {
+        let config = parse("[completion.git]\ndynamic = true\n");
+
+        assert!(config.dynamic_completion("git"));
+    }
+
+    #[test]
+    fn test_include_merges_inline() {
+        let included = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(included.path(), "[history]\nmax_files = 3\n").unwrap();
+
+        let source = format!(
+            "[history]\nmax_size = 1000\n%include {}\n",
+            included.path().to_string_lossy()
+        );
+        let config = parse(&source);
+
+        assert_eq!(Some(1000), config.history_max_size());
+        assert_eq!(Some(3), config.history_max_files());
+    }
+}
@@ -1,11 +1,18 @@
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
 
+use crate::argv::error::ArgumentError;
+
 #[derive(Debug)]
 pub enum WrashError {
     NonZeroExit(i32),
     FailedIo(std::io::Error),
     Custom(String),
+
+    /// An [ArgumentError] raised while expanding a line, alongside the
+    /// original line it was raised against so it can be rendered with
+    /// [ArgumentError::annotate] instead of a bare message.
+    Expansion(ArgumentError, String),
 }
 
 impl Display for WrashError {
@@ -14,6 +21,7 @@ impl Display for WrashError {
             WrashError::NonZeroExit(n) => write!(f, "command exited with nonzero exit code '{}'", n),
             WrashError::FailedIo(err) => write!(f, "failed io operation: {}", err),
             WrashError::Custom(s) => write!(f, "{}", s),
+            WrashError::Expansion(err, source) => write!(f, "{}\n{}", err, err.annotate(source)),
         }
     }
 }
@@ -25,6 +33,9 @@ impl PartialEq for WrashError {
             // right now we don't care too much about the specifics of the error only that they are the right type
             (WrashError::FailedIo(left), WrashError::FailedIo(right)) => left.kind() == right.kind(),
             (WrashError::Custom(left), WrashError::Custom(right)) => left == right,
+            (WrashError::Expansion(left, left_src), WrashError::Expansion(right, right_src)) => {
+                left == right && left_src == right_src
+            }
             _ => false,
             // _ => self == other
         }
@@ -35,6 +46,7 @@ impl Error for WrashError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
             WrashError::FailedIo(err) => Some(err),
+            WrashError::Expansion(err, _) => Some(err),
             _ => None
         }
     }
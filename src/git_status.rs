@@ -0,0 +1,153 @@
+use std::path::Path;
+
+use git2::{Repository, StatusOptions};
+
+/// The default prompt format: branch (or abbreviated commit hash when
+/// detached), dirty-state markers, then ahead/behind counts.
+pub const DEFAULT_FORMAT: &str = " (%b%s%a)";
+
+/// Render the git status segment for `cwd` according to `format`.
+///
+/// `format` may contain the placeholders `%b` (branch or detached commit),
+/// `%s` (dirty markers) and `%a` (ahead/behind indicator); anything else is
+/// passed through unchanged. Returns an empty string whenever `cwd` is not
+/// inside a git repository, or repository discovery otherwise fails, so
+/// non-git sessions see no prompt change at all.
+pub fn segment(cwd: &Path, format: &str) -> String {
+    let repo = match Repository::discover(cwd) {
+        Ok(repo) => repo,
+        Err(_) => return String::new(),
+    };
+
+    format
+        .replace("%b", &branch_segment(&repo))
+        .replace("%s", &status_segment(&repo))
+        .replace("%a", &ahead_behind_segment(&repo))
+}
+
+/// The current branch name, or `:`-prefixed abbreviated commit hash when
+/// `HEAD` is detached.
+fn branch_segment(repo: &Repository) -> String {
+    let head = match repo.head() {
+        Ok(head) => head,
+        Err(_) => return String::new(),
+    };
+
+    if let Some(name) = head.shorthand() {
+        if name != "HEAD" {
+            return name.to_string();
+        }
+    }
+
+    match head.peel_to_commit() {
+        Ok(commit) => format!(":{}", &commit.id().to_string()[..7]),
+        Err(_) => String::new(),
+    }
+}
+
+/// Dirty-state markers, in the order starship's `git_status` module uses
+/// them: untracked (`?`), modified (`!`), staged-new (`+`), renamed (`»`),
+/// staged-deletion (`✘`), conflicted (`=`), and stash presence (`$`).
+fn status_segment(repo: &Repository) -> String {
+    let mut options = StatusOptions::new();
+    options.include_untracked(true);
+
+    let statuses = match repo.statuses(Some(&mut options)) {
+        Ok(statuses) => statuses,
+        Err(_) => return String::new(),
+    };
+
+    let mut untracked = false;
+    let mut modified = false;
+    let mut staged_new = false;
+    let mut renamed = false;
+    let mut staged_deleted = false;
+    let mut conflicted = false;
+
+    for entry in statuses.iter() {
+        let status = entry.status();
+
+        untracked |= status.is_wt_new();
+        modified |= status.is_wt_modified();
+        staged_new |= status.is_index_new();
+        renamed |= status.is_index_renamed() || status.is_wt_renamed();
+        staged_deleted |= status.is_index_deleted();
+        conflicted |= status.is_conflicted();
+    }
+
+    let has_stash = repo
+        .find_reference("refs/stash")
+        .is_ok();
+
+    let mut markers = String::new();
+
+    if untracked {
+        markers.push('?');
+    }
+    if modified {
+        markers.push('!');
+    }
+    if staged_new {
+        markers.push('+');
+    }
+    if renamed {
+        markers.push('»');
+    }
+    if staged_deleted {
+        markers.push('✘');
+    }
+    if conflicted {
+        markers.push('=');
+    }
+    if has_stash {
+        markers.push('$');
+    }
+
+    markers
+}
+
+/// Ahead/behind indicator relative to the current branch's upstream:
+/// `⇡N` ahead, `⇣N` behind, `⇕⇡N⇣M` diverged, or empty with no upstream.
+fn ahead_behind_segment(repo: &Repository) -> String {
+    let head = match repo.head() {
+        Ok(head) => head,
+        Err(_) => return String::new(),
+    };
+
+    let local_oid = match head.target() {
+        Some(oid) => oid,
+        None => return String::new(),
+    };
+
+    let branch_name = match head.shorthand() {
+        Some(name) if name != "HEAD" => name,
+        _ => return String::new(),
+    };
+
+    let branch = match repo.find_branch(branch_name, git2::BranchType::Local) {
+        Ok(branch) => branch,
+        Err(_) => return String::new(),
+    };
+
+    let upstream = match branch.upstream() {
+        Ok(upstream) => upstream,
+        Err(_) => return String::new(),
+    };
+
+    let upstream_oid = match upstream.get().target() {
+        Some(oid) => oid,
+        None => return String::new(),
+    };
+
+    let (ahead, behind) = match repo.graph_ahead_behind(local_oid, upstream_oid) {
+        Ok(counts) => counts,
+        Err(_) => return String::new(),
+    };
+
+    match (ahead, behind) {
+        (0, 0) => String::new(),
+        (ahead, 0) => format!("⇡{}", ahead),
+        (0, behind) => format!("⇣{}", behind),
+        (ahead, behind) => format!("⇕⇡{}⇣{}", ahead, behind),
+    }
+}
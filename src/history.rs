@@ -1,35 +1,87 @@
 use std::fs::{self, File};
 use std::io::{ErrorKind, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use crate::session::SessionMode;
 use crate::WrashError;
 
+/// The on-disk representation used by `history export`/`history import`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HistoryFormat {
+    /// The full entries (command, base, mode, timestamp), serialized as JSON.
+    Json,
+
+    /// Just the rendered command text ([HistoryEntry::get_command]), one per
+    /// line, without the `N: ` index the `history` builtin prints before
+    /// each entry; importing this form loses the base, mode, and timestamp
+    /// metadata.
+    Text,
+}
+
+impl FromStr for HistoryFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(HistoryFormat::Json),
+            "text" => Ok(HistoryFormat::Text),
+            _ => Err(()),
+        }
+    }
+}
+
 /// A single entry into history, providing the command run and some meta-data
 /// describing it.
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct HistoryEntry {
     pub argv: String,
     pub base: Option<String>,
     pub mode: SessionMode,
     pub is_builtin: bool,
+
+    /// Seconds since the Unix epoch at which this entry was recorded.
+    /// Defaulted to `0` on deserialize so history files written before this
+    /// field existed still load.
+    #[serde(default)]
+    pub timestamp: u64,
+
+    /// The exit code the command (or builtin) finished with. Defaulted to
+    /// `0` on deserialize so history files written before this field existed
+    /// still load.
+    #[serde(default)]
+    pub exit_code: i32,
+
+    /// Wall-clock time the command took to run, in milliseconds. Defaulted
+    /// to `0` on deserialize so history files written before this field
+    /// existed still load.
+    #[serde(default)]
+    pub duration_ms: u64,
 }
 
 impl HistoryEntry {
     /// Construct a new [HistoryEntity] where [argv] contains the contents argv
     /// as a single String, [base] is the wrapped base command if there is one,
-    /// and [mode] is the shell execution mode.
+    /// [mode] is the shell execution mode, [timestamp] is the seconds since
+    /// the Unix epoch at which the command was run, [exit_code] is the code
+    /// it finished with, and [duration_ms] is how long it took to run.
     pub fn new(
         argv: String,
         base: Option<String>,
         mode: SessionMode,
         is_builtin: bool,
+        timestamp: u64,
+        exit_code: i32,
+        duration_ms: u64,
     ) -> HistoryEntry {
         HistoryEntry {
             argv,
             base,
             mode,
             is_builtin,
+            timestamp,
+            exit_code,
+            duration_ms,
         }
     }
 
@@ -41,12 +93,30 @@ impl HistoryEntry {
     }
 }
 
+/// Default number of rotated history files (`history.yaml.1`, `.2`, ...) kept
+/// around before the oldest is deleted.
+const DEFAULT_MAX_FILES: u32 = 5;
+
 #[derive(PartialEq, Debug)]
 pub struct History {
     history: Vec<HistoryEntry>,
 
     // ideally would  be an &Path rather than PathBuf
     path: Option<PathBuf>,
+
+    /// The size in bytes `history.yaml` may grow to before it is rotated on
+    /// the next [History::sync]. `None` disables rotation entirely.
+    max_size: Option<u64>,
+
+    /// How many rotated history files to keep before the oldest is deleted.
+    max_files: u32,
+
+    /// How many of `history`'s leading entries are already represented on
+    /// disk - either in the live file or in a file a previous [History::sync]
+    /// rotated out. Only the entries past this point need to be (re)written
+    /// after a rotation, so the same entries don't end up duplicated between
+    /// the freshly-rotated file and the new live file.
+    synced_len: usize,
 }
 
 /// Provides an abstraction around the shell's previously run commands.
@@ -57,6 +127,10 @@ impl History {
 
     /// Creates a new `History` value using $XDG_DATA_HOME/wrash/history as the
     /// history file.
+    ///
+    /// Rotation is disabled by default; use [History::max_size] and
+    /// [History::max_files] to enable it (`wrapped_main` applies the
+    /// `[history]` config thresholds that way after calling this).
     pub fn new() -> Result<History, WrashError> {
         match History::find_history_file() {
             Some(path) => History::with_file(path),
@@ -67,27 +141,63 @@ impl History {
         }
     }
 
-    /// Construct a new `History` file using the given file as the source. If
-    /// the file cold not be found or read, the history is created empty, and
-    /// the target file is also created.
-    fn with_file(path: PathBuf) -> Result<History, WrashError> {
-        let s = match fs::read_to_string(path.as_path()) {
+    /// Set the size in bytes `history.yaml` may grow to before it is rotated
+    /// on the next [History::sync]. `None` disables rotation entirely.
+    pub fn max_size(mut self, max_size: Option<u64>) -> History {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Set how many rotated history files (`history.yaml.1`, `.2`, ...) to
+    /// keep before the oldest is deleted.
+    pub fn max_files(mut self, max_files: u32) -> History {
+        self.max_files = max_files;
+        self
+    }
+
+    /// Read the entries out of a single history file, empty if it has no
+    /// content. Used both for the live file and for merging rotated ones.
+    fn read_entries(path: &Path) -> Result<Vec<HistoryEntry>, WrashError> {
+        let s = match fs::read_to_string(path) {
             Ok(s) => s,
             Err(err) => return Err(WrashError::FailedIo(err)),
         };
 
-        let history = if s.is_empty() {
-            vec![]
+        if s.is_empty() {
+            Ok(vec![])
         } else {
-            match serde_yaml::from_str(s.as_str()) {
-                Ok(history) => history,
-                Err(err) => return Err(WrashError::Custom(err.to_string())),
+            serde_yaml::from_str(s.as_str()).map_err(|err| WrashError::Custom(err.to_string()))
+        }
+    }
+
+    /// Construct a new `History` file using the given file as the source. If
+    /// the file cold not be found or read, the history is created empty, and
+    /// the target file is also created.
+    ///
+    /// Any rotated files (`path.1`, `path.2`, ...) up to [DEFAULT_MAX_FILES]
+    /// that exist alongside `path` are merged in, oldest first, so `iter()`
+    /// still sees recent entries a rotation pushed out of the live file.
+    fn with_file(path: PathBuf) -> Result<History, WrashError> {
+        let mut history = vec![];
+
+        for n in (1..=DEFAULT_MAX_FILES).rev() {
+            let rotated = rotated_path(&path, n);
+
+            if rotated.exists() {
+                history.append(&mut History::read_entries(&rotated)?);
             }
-        };
+        }
+
+        history.append(&mut History::read_entries(&path)?);
+
+        let synced_len = history.len();
 
         Ok(Self {
             history,
             path: Some(path),
+            max_size: None,
+            max_files: DEFAULT_MAX_FILES,
+            synced_len,
         })
     }
 
@@ -95,6 +205,9 @@ impl History {
         History {
             history: vec![],
             path: None,
+            max_size: None,
+            max_files: DEFAULT_MAX_FILES,
+            synced_len: 0,
         }
     }
 
@@ -105,8 +218,13 @@ impl History {
     /// Sync the current in-memory history with the history file.
     ///
     /// If the history is stored in memory only (self.path == None), this
-    /// method returns an error.
-    pub fn sync(&self) -> Result<(), WrashError> {
+    /// method returns an error. If a `max_size` is set and the existing file
+    /// already exceeds it, the file is rotated (see [rotate]) before the
+    /// fresh history is written - in which case only the entries accumulated
+    /// since the last sync are written to the new live file, since the rest
+    /// just moved into the rotated one. Without a rotation, the full history
+    /// is (re)written, as before.
+    pub fn sync(&mut self) -> Result<(), WrashError> {
         if self.path.is_none() {
             return Err(WrashError::FailedIo(std::io::Error::new(
                 std::io::ErrorKind::Other,
@@ -114,18 +232,36 @@ impl History {
             )));
         }
 
-        let s = serde_yaml::to_string(self.history.as_slice())
-            .expect("to-string should not have erred");
+        let path = self.path.as_ref().unwrap().clone();
+
+        let mut rotated = false;
 
-        let mut history_file = match File::create(self.path.as_ref().unwrap().as_path()) {
+        if let Some(max_size) = self.max_size {
+            if let Ok(metadata) = fs::metadata(&path) {
+                if metadata.len() > max_size {
+                    rotate(&path, self.max_files)?;
+                    rotated = true;
+                }
+            }
+        }
+
+        let to_write = if rotated {
+            &self.history[self.synced_len..]
+        } else {
+            self.history.as_slice()
+        };
+
+        let s = serde_yaml::to_string(to_write).expect("to-string should not have erred");
+
+        let mut history_file = match File::create(&path) {
             Ok(f) => f,
             Err(err) => match err.kind() {
                 ErrorKind::NotFound => {
-                    if let Some(parent) = self.path.as_ref().unwrap().parent() {
+                    if let Some(parent) = path.parent() {
                         fs::create_dir_all(parent)?;
                     }
 
-                    File::create(self.path.as_ref().unwrap().as_path())?
+                    File::create(&path)?
                 }
                 _ => return Err(WrashError::FailedIo(err)),
             },
@@ -133,6 +269,8 @@ impl History {
 
         write!(history_file, "{}", s)?;
 
+        self.synced_len = self.history.len();
+
         Ok(())
     }
 
@@ -143,6 +281,123 @@ impl History {
             back_index: self.history.len(),
         }
     }
+
+    /// Entries recorded at or after `since` (seconds since the Unix epoch).
+    pub fn iter_since(&self, since: u64) -> impl DoubleEndedIterator<Item = &HistoryEntry> {
+        self.iter().filter(move |entry| entry.timestamp >= since)
+    }
+
+    /// Entries that finished with a non-zero exit code.
+    pub fn iter_failed(&self) -> impl DoubleEndedIterator<Item = &HistoryEntry> {
+        self.iter().filter(|entry| entry.exit_code != 0)
+    }
+
+    /// Entries whose base command matches `base`.
+    pub fn iter_for_base(&self, base: &str) -> impl DoubleEndedIterator<Item = &HistoryEntry> {
+        self.iter()
+            .filter(move |entry| entry.base.as_deref() == Some(base))
+    }
+
+    /// Write the current history to `path` in the given `format`. When
+    /// `dedup` is set, runs of consecutive entries with the same rendered
+    /// command are collapsed to their last occurrence before writing.
+    pub fn export(&self, path: &Path, format: HistoryFormat, dedup: bool) -> Result<(), WrashError> {
+        let mut entries = self.history.clone();
+
+        if dedup {
+            dedup_consecutive(&mut entries);
+        }
+
+        let s = match format {
+            HistoryFormat::Json => serde_json::to_string_pretty(&entries)
+                .map_err(|err| WrashError::Custom(err.to_string()))?,
+            HistoryFormat::Text => entries
+                .iter()
+                .map(HistoryEntry::get_command)
+                .collect::<Vec<String>>()
+                .join("\n"),
+        };
+
+        fs::write(path, s)?;
+
+        Ok(())
+    }
+
+    /// Read entries from `path` in the given `format` and append them to the
+    /// in-memory history. Entries read from [HistoryFormat::Text] carry no
+    /// base, mode, or timestamp metadata since that form only stores the
+    /// rendered command text.
+    pub fn import(&mut self, path: &Path, format: HistoryFormat) -> Result<(), WrashError> {
+        let s = fs::read_to_string(path)?;
+
+        let mut entries: Vec<HistoryEntry> = match format {
+            HistoryFormat::Json => {
+                serde_json::from_str(&s).map_err(|err| WrashError::Custom(err.to_string()))?
+            }
+            HistoryFormat::Text => s
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(|line| HistoryEntry::new(line.to_string(), None, SessionMode::Normal, false, 0, 0, 0))
+                .collect(),
+        };
+
+        self.history.append(&mut entries);
+
+        Ok(())
+    }
+}
+
+/// Collapse runs of consecutive entries whose rendered command is identical,
+/// keeping the last (most recent) entry of each run.
+fn dedup_consecutive(entries: &mut Vec<HistoryEntry>) {
+    // dedup_by drops `a` (the later entry) and keeps `b`, so swap them first
+    // to leave the most recent entry of the run in place
+    entries.dedup_by(|a, b| {
+        if a.get_command() == b.get_command() {
+            std::mem::swap(a, b);
+            true
+        } else {
+            false
+        }
+    });
+}
+
+/// `path` with `.n` appended, e.g. `history.yaml` + 1 -> `history.yaml.1`.
+fn rotated_path(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", n));
+
+    PathBuf::from(name)
+}
+
+/// Rotate `path` out of the way: drop whatever rotated file currently sits at
+/// `max_files` (the oldest kept), shift `.1..max_files-1` up one slot each,
+/// then rename `path` itself to `.1`. Each rename is atomic, so a crash
+/// mid-rotation can at worst lose one rotated file, never corrupt the live
+/// one.
+fn rotate(path: &Path, max_files: u32) -> Result<(), WrashError> {
+    if max_files == 0 {
+        fs::remove_file(path)?;
+        return Ok(());
+    }
+
+    let oldest = rotated_path(path, max_files);
+
+    if oldest.exists() {
+        fs::remove_file(&oldest)?;
+    }
+
+    for n in (1..max_files).rev() {
+        let from = rotated_path(path, n);
+
+        if from.exists() {
+            fs::rename(from, rotated_path(path, n + 1))?;
+        }
+    }
+
+    fs::rename(path, rotated_path(path, 1))?;
+
+    Ok(())
 }
 
 pub struct HistoryIterator<'history> {
@@ -181,7 +436,7 @@ impl<'history> DoubleEndedIterator for HistoryIterator<'history> {
 
 #[cfg(test)]
 mod test {
-    use crate::history::HistoryEntry;
+    use crate::history::{rotated_path, HistoryEntry, DEFAULT_MAX_FILES};
     use crate::{History, SessionMode, WrashError};
     use std::fs::read_to_string;
     use std::io::Write;
@@ -206,16 +461,25 @@ mod test {
                     Some("cmd".to_string()),
                     SessionMode::Wrapped,
                     false,
+                    0,
+                    0,
+                    0,
                 ),
                 HistoryEntry::new(
                     "othersubcmd --verbose ARG".to_string(),
                     None,
                     SessionMode::Normal,
                     false,
+                    0,
+                    0,
+                    0,
                 ),
-                HistoryEntry::new("mode".to_string(), None, SessionMode::Wrapped, true),
+                HistoryEntry::new("mode".to_string(), None, SessionMode::Wrapped, true, 0, 0, 0),
             ],
             path: Some(history_path.clone()),
+            max_size: None,
+            max_files: DEFAULT_MAX_FILES,
+            synced_len: 3,
         };
         let actual = History::with_file(history_path)?;
 
@@ -250,4 +514,39 @@ mod test {
 
         Ok(())
     }
+
+    fn entry(name: &str) -> HistoryEntry {
+        HistoryEntry::new(name.to_string(), None, SessionMode::Normal, false, 0, 0, 0)
+    }
+
+    #[test]
+    fn test_sync_rotation_does_not_duplicate_entries() -> Result<(), Box<dyn std::error::Error>> {
+        let file = NamedTempFile::new()?;
+        let path = file.path().to_path_buf();
+
+        let mut history = History {
+            history: vec![],
+            path: Some(path.clone()),
+            max_size: Some(1),
+            max_files: DEFAULT_MAX_FILES,
+            synced_len: 0,
+        };
+
+        history.push(entry("cmd1"));
+        history.sync()?;
+
+        history.push(entry("cmd2"));
+        history.sync()?;
+
+        assert!(rotated_path(&path, 1).exists());
+
+        let reloaded = History::with_file(path)?;
+
+        assert_eq!(
+            vec![entry("cmd1"), entry("cmd2")],
+            reloaded.iter().cloned().collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
 }
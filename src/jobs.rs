@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::process::Child;
+use std::sync::{Arc, Mutex};
+
+/// The running state of a single background [Job].
+///
+/// There is no suspended state: wrash has no job-suspension support (no
+/// Ctrl-Z/SIGTSTP path that could ever produce one), so a tracked job is
+/// always either still running or finished.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JobState {
+    Running,
+    Done(i32),
+}
+
+impl Display for JobState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobState::Running => write!(f, "Running"),
+            JobState::Done(code) => write!(f, "Done({})", code),
+        }
+    }
+}
+
+/// A single backgrounded command, its original command line, and the
+/// spawned child used to track and reap it.
+pub struct Job {
+    pub child: Child,
+    pub command: String,
+    pub state: JobState,
+
+    /// The child's combined stdout/stderr, drained continuously by reader
+    /// threads spawned alongside the child so the buffer is populated
+    /// whether or not anyone has asked for it yet.
+    pub output: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Job {
+    /// A snapshot of the job's captured output so far, valid to read whether
+    /// the job is still running or has already finished.
+    pub fn output(&self) -> Vec<u8> {
+        self.output.lock().unwrap().clone()
+    }
+}
+
+/// A table of the currently tracked background [Job]s, keyed by a small
+/// integer job id assigned in the order jobs are inserted.
+pub struct Jobs {
+    next_id: usize,
+    jobs: BTreeMap<usize, Job>,
+}
+
+impl Jobs {
+    pub fn new() -> Jobs {
+        Jobs {
+            next_id: 1,
+            jobs: BTreeMap::new(),
+        }
+    }
+
+    /// Track `child` as a new background job, returning the id it was
+    /// assigned.
+    pub fn insert(&mut self, child: Child, command: String, output: Arc<Mutex<Vec<u8>>>) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.jobs.insert(
+            id,
+            Job {
+                child,
+                command,
+                state: JobState::Running,
+                output,
+            },
+        );
+
+        id
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.get_mut(&id)
+    }
+
+    /// Stop tracking the job with the given id, returning it if it existed.
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        self.jobs.remove(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&usize, &Job)> {
+        self.jobs.iter()
+    }
+
+    /// Poll every running job with `try_wait`, removing and returning any
+    /// that have finished since the last call.
+    pub fn poll(&mut self) -> Vec<(usize, Job)> {
+        let done_ids: Vec<usize> = self
+            .jobs
+            .iter_mut()
+            .filter_map(|(id, job)| {
+                if job.state != JobState::Running {
+                    return None;
+                }
+
+                match job.child.try_wait() {
+                    Ok(Some(_)) => Some(*id),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        done_ids
+            .into_iter()
+            .filter_map(|id| self.jobs.remove(&id).map(|job| (id, job)))
+            .collect()
+    }
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        Jobs::new()
+    }
+}
@@ -4,32 +4,84 @@ extern crate clap;
 #[macro_use]
 extern crate serde_derive;
 
+mod argv;
 mod builtins;
 mod completion;
+mod config;
 mod error;
+mod git_status;
 mod history;
+mod jobs;
 mod session;
+mod watch;
 
 use std::{env, thread};
 use std::io::{self, Read, Write};
-use std::process::{Child, Command, Stdio};
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicI32, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use command_group::CommandGroup;
 
 use crate::error::WrashError;
 use clap::Arg;
 
+use crate::config::Config;
 use crate::history::History;
 use crate::session::{Session, SessionMode};
 
-/// Generate the command prompt
-///
-/// todo: allow some user configurability
-fn prompt() -> String {
-    format!("[{}] $ ", env::var("USER").unwrap())
+/// The default prompt format, used unless the `[prompt]` `format` key is set
+/// in the user's config (see [config]).
+pub const DEFAULT_PROMPT_FORMAT: &str = "[%u]%g%j $ ";
+
+/// Generate the command prompt from `session`'s configured format (see
+/// [Session::prompt_format]), substituting `%u` for the current user, `%g`
+/// for `session`'s git status segment (see [git_status]), and `%j` for a
+/// `[Nj]` indicator when any background jobs are still running.
+fn prompt(session: &Session) -> String {
+    let jobs = match session.running_job_count() {
+        0 => String::new(),
+        n => format!("[{}j]", n),
+    };
+
+    session
+        .prompt_format()
+        .replace("%u", &env::var("USER").unwrap_or_default())
+        .replace("%g", &session.git_prompt_segment())
+        .replace("%j", &jobs)
+}
+
+/// The pgid of the process group currently running in the foreground, or `0`
+/// if none is. Read by the `ctrlc` handler installed in [wrapped_main] so a
+/// received signal can be forwarded to the whole group instead of being
+/// swallowed, and written by [run] around the span it waits on a child.
+static FOREGROUND_PGID: AtomicI32 = AtomicI32::new(0);
+
+/// Translate a child's exit status into the shell's exit code, using the
+/// common `128 + signo` convention for signal-terminated children instead of
+/// guessing at a fixed code.
+fn exit_code(status: ExitStatus) -> i32 {
+    status.code().unwrap_or_else(|| 128 + status.signal().unwrap_or(0))
 }
 
-fn run(command: &str, args: &[String]) -> Result<(), WrashError> {
-    let proc = Command::new(command).args(args).spawn();
+/// Run `command` in the foreground, piping its stdout and stderr through to
+/// the real ones while concurrently capturing the same interleaved bytes
+/// into `session` (see [Session::last_output]) so a later config option can
+/// tee them into a log file.
+///
+/// Both streams are drained by their own reader thread so neither can fill
+/// its OS pipe buffer and stall the child while we're blocked reading the
+/// other - the same concurrent-drain invariant [run_background] already
+/// relies on.
+fn run(session: &mut Session, command: &str, args: &[String]) -> Result<(), WrashError> {
+    let proc = Command::new(command)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .group_spawn();
 
     let code = match proc {
         Err(err) => {
@@ -37,15 +89,74 @@ fn run(command: &str, args: &[String]) -> Result<(), WrashError> {
 
             -1
         }
-        Ok(mut child) => match child.wait() {
-            // todo: better handle signal interrupts here (don't just return 255)
-            Ok(status) => status.code().unwrap_or(255),
-            Err(err) => {
-                eprintln!("command '{}' never started: {}", command, err);
+        Ok(mut child) => {
+            FOREGROUND_PGID.store(child.id() as i32, Ordering::SeqCst);
+
+            let output = Arc::new(Mutex::new(Vec::new()));
+
+            let stdout_handle = child.stdout.take().map(|mut stream| {
+                let output = Arc::clone(&output);
+
+                thread::spawn(move || {
+                    let mut buf = [0u8; 4096];
+                    let mut stdout = io::stdout();
+
+                    while let Ok(n) = stream.read(&mut buf) {
+                        if n == 0 {
+                            break;
+                        }
+
+                        let _ = stdout.write_all(&buf[..n]);
+                        output.lock().unwrap().extend_from_slice(&buf[..n]);
+                    }
+                })
+            });
+
+            let stderr_handle = child.stderr.take().map(|mut stream| {
+                let output = Arc::clone(&output);
 
-                -3
+                thread::spawn(move || {
+                    let mut buf = [0u8; 4096];
+                    let mut stderr = io::stderr();
+
+                    while let Ok(n) = stream.read(&mut buf) {
+                        if n == 0 {
+                            break;
+                        }
+
+                        let _ = stderr.write_all(&buf[..n]);
+                        output.lock().unwrap().extend_from_slice(&buf[..n]);
+                    }
+                })
+            });
+
+            let code = match child.wait() {
+                Ok(status) => exit_code(status),
+                Err(err) => {
+                    eprintln!("command '{}' never started: {}", command, err);
+
+                    -3
+                }
+            };
+
+            if let Some(handle) = stdout_handle {
+                let _ = handle.join();
             }
-        },
+
+            if let Some(handle) = stderr_handle {
+                let _ = handle.join();
+            }
+
+            FOREGROUND_PGID.store(0, Ordering::SeqCst);
+
+            let output = Arc::try_unwrap(output)
+                .map(|m| m.into_inner().unwrap())
+                .unwrap_or_default();
+
+            session.set_last_output(output);
+
+            code
+        }
     };
 
     if code == 0 {
@@ -55,6 +166,228 @@ fn run(command: &str, args: &[String]) -> Result<(), WrashError> {
     }
 }
 
+/// Resolve `name` to an executable path for `mode normal` dispatch.
+///
+/// Bare names are looked up on `PATH` with the `which` crate, which (unlike
+/// handing the name straight to [Command]) never implicitly considers the
+/// current directory — on Windows, `Command` does, so a file named after a
+/// common command sitting in cwd would otherwise run silently. If `name`
+/// already contains a path separator the caller is explicitly naming a file,
+/// so it is resolved relative to cwd as usual.
+pub(crate) fn resolve_command(name: &str) -> Option<PathBuf> {
+    if name.contains(std::path::MAIN_SEPARATOR) {
+        let path = PathBuf::from(name);
+
+        return if path.is_file() { Some(path) } else { None };
+    }
+
+    which::which(name).ok()
+}
+
+/// Spawn `command` without waiting on it, for backgrounding with a trailing
+/// `&`. Its stdout and stderr are piped and drained by background reader
+/// threads into the returned buffer, so `jobs output <id>` has something to
+/// show once the job finishes without the REPL blocking on it meanwhile.
+fn run_background(command: &str, args: &[String]) -> Result<(Child, Arc<Mutex<Vec<u8>>>), WrashError> {
+    let mut child = match Command::new(command)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => {
+            eprintln!("Error starting '{}': {}", command, err);
+
+            return Err(WrashError::NonZeroExit(-1));
+        }
+    };
+
+    let output = Arc::new(Mutex::new(Vec::new()));
+
+    if let Some(mut stdout) = child.stdout.take() {
+        let output = Arc::clone(&output);
+
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+
+            while let Ok(n) = stdout.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+
+                output.lock().unwrap().extend_from_slice(&buf[..n]);
+            }
+        });
+    }
+
+    if let Some(mut stderr) = child.stderr.take() {
+        let output = Arc::clone(&output);
+
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+
+            while let Ok(n) = stderr.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+
+                output.lock().unwrap().extend_from_slice(&buf[..n]);
+            }
+        });
+    }
+
+    Ok((child, output))
+}
+
+/// Dispatch a single already-assembled command line against `session`,
+/// running builtins or handing off to `run` depending on the session's mode.
+///
+/// This is the same path used by the interactive loop in [wrapped_main], and
+/// is also used by the `source` builtin to feed lines read from a file
+/// through without duplicating the dispatch logic.
+pub(crate) fn dispatch(
+    cmd: &str,
+    session: &mut Session,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+    should_continue: &mut bool,
+) -> Result<(), WrashError> {
+    let expanded_aliases = match session.expand_aliases(cmd) {
+        Ok(line) => line,
+        Err(err) => {
+            eprintln!("Error: {}\n{}", err, err.annotate(cmd));
+            return Ok(());
+        }
+    };
+
+    let mut argv = match argv::expand::expand(&expanded_aliases) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("Error: {}\n{}", err, err.annotate(&expanded_aliases));
+            return Ok(());
+        }
+    };
+
+    if argv.is_empty() {
+        return Ok(());
+    }
+
+    // a trailing `&` backgrounds the command instead of waiting on it; it
+    // only applies to commands handed off to the wrapped base or the system,
+    // not to builtins
+    let background = argv.last().map(String::as_str) == Some("&");
+
+    if background {
+        argv.pop();
+    }
+
+    if argv.is_empty() {
+        return Ok(());
+    }
+
+    let base = session.get_base();
+
+    let started_at = Instant::now();
+
+    let result = match argv[0].as_str() {
+        "exit" => {
+            // todo: differentiate between successful run of exit and failed argument parsing for exit
+            *should_continue = false;
+            builtins::exit(&argv)
+        }
+        "cd" => builtins::cd(stderr, session, &argv),
+        "mode" => builtins::mode(stdout, stderr, session, &argv),
+        "?" => builtins::help(&argv),
+        "history" => builtins::history(stdout, stderr, session, &argv),
+        "export" => builtins::export(stdout, &argv),
+        "unset" => builtins::unset(&argv),
+        "alias" => builtins::alias(stdout, session, &argv),
+        "unalias" => builtins::unalias(session, &argv),
+        "source" => builtins::source(stdout, stderr, session, &argv),
+        "jobs" => builtins::jobs(stdout, stderr, session, &argv),
+        "fg" => builtins::fg(stdout, stderr, session, &argv),
+        "bg" => builtins::bg(stdout, stderr, session, &argv),
+        "wait" => builtins::wait(stderr, session, &argv),
+        "watch" => builtins::watch(session, &argv),
+        "which" => builtins::which(stdout, stderr, &argv),
+        _ if background => {
+            let resolved: Result<(String, Vec<String>), WrashError> = match session.mode() {
+                SessionMode::Wrapped => Ok((base.clone(), argv.clone())),
+                SessionMode::Normal => match resolve_command(&argv[0]) {
+                    Some(path) => Ok((path.to_string_lossy().to_string(), argv[1..].to_vec())),
+                    None => {
+                        eprintln!("{}: command not found", argv[0]);
+
+                        Err(WrashError::NonZeroExit(127))
+                    }
+                },
+            };
+
+            resolved.and_then(|(command, args)| {
+                run_background(&command, &args).map(|(child, output)| {
+                    let id = session.jobs().insert(child, cmd.trim().to_string(), output);
+
+                    let _ = writeln!(stdout, "[{}] {}", id, session.jobs().get_mut(id).unwrap().child.id());
+                })
+            })
+        }
+        _ => match session.mode() {
+            SessionMode::Wrapped => run(session, base.as_str(), argv.as_slice()),
+            SessionMode::Normal => match resolve_command(&argv[0]) {
+                Some(path) => run(session, path.to_string_lossy().as_ref(), &argv[1..]),
+                None => {
+                    eprintln!("{}: command not found", argv[0]);
+
+                    Err(WrashError::NonZeroExit(127))
+                }
+            },
+        },
+    };
+
+    let exit_code = match &result {
+        Ok(()) => 0,
+        Err(WrashError::NonZeroExit(n)) => *n,
+        Err(_) => -1,
+    };
+
+    let duration_ms = started_at.elapsed().as_millis() as u64;
+
+    session.push_to_history(cmd, builtins::is_builtin(argv[0].as_str()), exit_code, duration_ms);
+
+    result
+}
+
+/// Source the per-base rc file (`~/.config/wrash/<base>.rc`) if one exists,
+/// feeding it through [builtins::source] so a user's `mode`/`cd`/`export`
+/// setup for this base command is applied before the first prompt.
+fn source_rc_file(
+    session: &mut Session,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) {
+    let dirs = match directories::ProjectDirs::from("", "", "wrash") {
+        Some(dirs) => dirs,
+        None => return,
+    };
+
+    let rc_path = dirs.config_dir().join(format!("{}.rc", session.get_base()));
+
+    if !rc_path.is_file() {
+        return;
+    }
+
+    let _ = builtins::source(
+        stdout,
+        stderr,
+        session,
+        &[
+            "source".to_string(),
+            rc_path.to_string_lossy().to_string(),
+        ],
+    );
+}
+
 fn wrapped_main() -> Result<(), WrashError> {
     let matches = app_from_crate!()
         .arg(
@@ -71,7 +404,15 @@ fn wrapped_main() -> Result<(), WrashError> {
         )
         .get_matches();
 
-    let history = match History::new() {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Could not load config: {}\ncontinuing with defaults", err);
+            Config::empty()
+        }
+    };
+
+    let mut history = match History::new() {
         Ok(history) => history,
         Err(err) => {
             eprintln!("Could not establish proper history: {}\ncontinuing with in memory error (you will not be able to sync history changes", err);
@@ -79,10 +420,26 @@ fn wrapped_main() -> Result<(), WrashError> {
         }
     };
 
+    if let Some(max_size) = config.history_max_size() {
+        history = history.max_size(Some(max_size));
+    }
+
+    if let Some(max_files) = config.history_max_files() {
+        history = history.max_files(max_files);
+    }
+
     let base = matches.value_of("cmd").unwrap();
-    let is_frozen = matches.is_present("is_frozen");
+    let is_frozen = matches.is_present("is_frozen") || config.is_frozen().unwrap_or(false);
+    let mode = config.default_mode().unwrap_or(SessionMode::Wrapped);
 
-    let mut session = Session::new(history, is_frozen, base, SessionMode::Wrapped);
+    let mut session = Session::new(history, is_frozen, base, mode);
+
+    if let Some(format) = config.prompt_format() {
+        session.set_prompt_format(format.to_string());
+    }
+
+    session.set_completion_matchers(config.completion_matchers(base));
+    session.set_dynamic_completion_enabled(config.dynamic_completion(base));
 
     let mut should_continue = true;
     let mut result = Ok(());
@@ -90,11 +447,28 @@ fn wrapped_main() -> Result<(), WrashError> {
     let mut stdout = std::io::stdout();
     let mut stderr = std::io::stderr();
 
-    ctrlc::set_handler(|| { });
+    source_rc_file(&mut session, &mut stdout, &mut stderr);
+
+    // Ctrl-C (and friends) should interrupt the wrapped command, not wrash
+    // itself; forward the signal to whatever process group is currently in
+    // the foreground and let the REPL loop right back around.
+    ctrlc::set_handler(|| {
+        let pgid = FOREGROUND_PGID.load(Ordering::SeqCst);
+
+        if pgid != 0 {
+            unsafe {
+                libc::kill(-pgid, libc::SIGINT);
+            }
+        }
+    });
 
     while should_continue {
         let _ = io::stdout().flush();
 
+        for (id, job) in session.jobs().poll() {
+            println!("[{}] Done\t{}", id, job.command);
+        }
+
         // todo: we will likely want to do the splitting ourselves or add post-processing to allow for globbing so that we can handle globs
         let cmd = match session.take_input() {
             Ok(c) => c,
@@ -104,35 +478,13 @@ fn wrapped_main() -> Result<(), WrashError> {
             }
         };
 
-        let argv = match shlex::split(cmd.as_str()) {
-            Some(args) => args,
-            None => {
-                eprintln!("Error splitting command line arguments");
-                continue;
-            }
-        };
-
-        if argv.is_empty() {
-            continue;
-        }
-
-        result = match argv[0].as_str() {
-            "exit" => {
-                // todo: differentiate between successful run of exit and failed argument parsing for exit
-                should_continue = false;
-                builtins::exit(&argv)
-            }
-            "cd" => builtins::cd(&mut stderr, &argv),
-            "mode" => builtins::mode(&mut stdout, &mut stderr, &mut session, &argv),
-            "?" => builtins::help(&argv),
-            "history" => builtins::history(&mut stdout, &mut stderr, &mut session, &argv),
-            _ => match session.mode() {
-                SessionMode::Wrapped => run(base, argv.as_slice()),
-                SessionMode::Normal => run(argv[0].as_str(), &argv[1..]),
-            },
-        };
-
-        session.push_to_history(cmd.as_str(), builtins::is_builtin(argv[0].as_str()));
+        result = dispatch(
+            cmd.as_str(),
+            &mut session,
+            &mut stdout,
+            &mut stderr,
+            &mut should_continue,
+        );
     }
 
     result
@@ -144,6 +496,7 @@ fn main() {
             WrashError::NonZeroExit(n) => std::process::exit(n),
             WrashError::FailedIo(err) => eprintln!("Error: {}", err),
             WrashError::Custom(s) => println!("Error: {}", s),
+            WrashError::Expansion(err, source) => println!("Error: {}\n{}", err, err.annotate(&source)),
         }
     }
 }
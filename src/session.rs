@@ -1,9 +1,19 @@
 use std::cmp::{max, Ordering};
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
 use std::fmt::{Display, Formatter};
+use std::fs;
 use std::io::{self, Write};
-use std::path::{Component, Path};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tempfile::NamedTempFile;
+
+use directories::UserDirs;
+
+use git2::Repository;
 
 use termion::clear::{AfterCursor, All};
 use termion::cursor::{Goto, Right};
@@ -13,8 +23,12 @@ use termion::raw::IntoRawMode;
 
 use faccess::PathExt;
 
+use crate::argv;
+use crate::argv::error::ArgumentError;
 use crate::completion;
-use crate::history::{History, HistoryEntry, HistoryIterator};
+use crate::completion::Matchers;
+use crate::history::{History, HistoryEntry, HistoryFormat, HistoryIterator};
+use crate::jobs::Jobs;
 
 use crate::prompt;
 
@@ -64,11 +78,200 @@ fn get_next_boundary(buffer: &str, cursor_offset: usize) -> usize {
     position
 }
 
+/// Format a path as a completion candidate, appending a trailing
+/// [std::path::MAIN_SEPARATOR] when it names a directory so continued
+/// tabbing can descend into it without an intervening space.
+fn format_completion(path: &Path) -> String {
+    let name = path.to_string_lossy().to_string();
+
+    if path.is_dir() {
+        format!("{}{}", name, std::path::MAIN_SEPARATOR)
+    } else {
+        name
+    }
+}
+
+/// Expand a leading `~` or `~user` in `prefix` to that user's home
+/// directory. Returns `None` if `prefix` doesn't start with `~`, or if the
+/// named user (or the current user, for a bare `~`) can't be resolved.
+fn expand_tilde(prefix: &str) -> Option<String> {
+    let rest = prefix.strip_prefix('~')?;
+    let (name, tail) = match rest.find('/') {
+        Some(idx) => (&rest[..idx], &rest[idx..]),
+        None => (rest, ""),
+    };
+
+    let home = if name.is_empty() {
+        UserDirs::new().map(|dirs| dirs.home_dir().to_path_buf())
+    } else {
+        users::get_user_by_name(name).map(|user| user.home_dir().to_path_buf())
+    }?;
+
+    Some(format!("{}{}", home.to_string_lossy(), tail))
+}
+
+/// Expand every "ndots" path component in `prefix` - a run of N >= 3 dots -
+/// into N-1 `..` components (`...` -> `../..`, `....` -> `../../..`), both
+/// as a whole component and embedded in a longer path like `.../foo`.
+/// Plain `.` and `..` are left untouched. Returns `None` if `prefix`
+/// contains no ndots component.
+fn expand_ndots(prefix: &str) -> Option<String> {
+    let mut changed = false;
+
+    let expanded: Vec<String> = prefix
+        .split('/')
+        .map(|component| {
+            if component.len() >= 3 && component.chars().all(|c| c == '.') {
+                changed = true;
+                vec![".."; component.len() - 1].join("/")
+            } else {
+                component.to_string()
+            }
+        })
+        .collect();
+
+    if changed {
+        Some(expanded.join("/"))
+    } else {
+        None
+    }
+}
+
+/// Expand `~`/`~user` and ndots components in `prefix`; see [expand_tilde]
+/// and [expand_ndots]. Returns `None` if neither form is present, so the
+/// caller can search using the original prefix text unchanged.
+fn expand_prefix(prefix: &str) -> Option<String> {
+    let tilde_expanded = expand_tilde(prefix);
+    let ndots_expanded = expand_ndots(tilde_expanded.as_deref().unwrap_or(prefix));
+
+    ndots_expanded.or(tilde_expanded)
+}
+
+/// Rewrite `candidate`'s leading `expanded` prefix (if any) back to
+/// `original`, so completions for an expanded prefix like `~/Doc` still
+/// read as `~/Documents/` rather than the fully-resolved path.
+fn restore_original_prefix(candidate: String, original: &str, expanded: Option<&str>) -> String {
+    match expanded {
+        Some(expanded) if candidate.starts_with(expanded) => {
+            format!("{}{}", original, &candidate[expanded.len()..])
+        }
+        _ => candidate,
+    }
+}
+
+/// Resolve `input` against `pwd` when it's relative, rather than the
+/// process cwd, so callers can search against a session's logical working
+/// directory even if it has diverged from `env::current_dir()`; see
+/// [Session::cwd].
+fn expand_path_with(input: &str, pwd: &Path) -> PathBuf {
+    let path = Path::new(input);
+
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        pwd.join(path)
+    }
+}
+
+/// Whether `path` contains a `.` or `..` component, used to decide whether a
+/// trailing separator on a `cd` target is safe to carry over onto the
+/// logical pwd; see [Session::change_directory].
+fn has_dot_segments(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .any(|component| matches!(component, Component::CurDir | Component::ParentDir))
+}
+
+/// Whether the word starting at `word_start` in `buffer` is the immediate
+/// argument to a passthrough wrapper - i.e. `buffer` has exactly one
+/// preceding word and that word is in `passthrough`. Used to complete
+/// `sudo <tab>` as a command rather than a filename; see
+/// [Session::add_passthrough_command].
+fn is_passthrough_target(buffer: &str, word_start: usize, passthrough: &BTreeSet<String>) -> bool {
+    let mut preceding = buffer[..word_start].split_whitespace();
+
+    match (preceding.next(), preceding.next()) {
+        (Some(first), None) => passthrough.contains(first),
+        _ => false,
+    }
+}
+
+/// How a completion entry's path is rendered back to the user.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompletionDisplay {
+    /// Joined onto the literal prefix the user typed, preserving `~`/ndots
+    /// shorthand (e.g. `~/Doc` -> `~/Documents/`). This is the default.
+    LiteralPrefix,
+
+    /// Relative to the session's logical working directory, ignoring
+    /// whatever shorthand the user typed.
+    Cwd,
+
+    /// Relative to the discovered project root - the nearest ancestor
+    /// directory containing a `.git` - falling back to [CompletionDisplay::Cwd]
+    /// when `cwd` isn't inside a repository.
+    Root,
+}
+
+impl Default for CompletionDisplay {
+    fn default() -> Self {
+        CompletionDisplay::LiteralPrefix
+    }
+}
+
+/// How typed characters are matched against candidate completions; see
+/// [completion::search_prefix].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompletionMatchMode {
+    /// Candidates must start with exactly what was typed. This is the
+    /// default.
+    ExactPrefix,
+
+    /// Like [CompletionMatchMode::ExactPrefix], but ignoring case.
+    CaseInsensitive,
+
+    /// Candidates need only contain the typed characters in order,
+    /// possibly with other characters between them (e.g. `afw` matches
+    /// `a_file_as_well`). Matches ranked higher by
+    /// [completion::fuzzy_score] are returned first.
+    Fuzzy,
+}
+
+impl Default for CompletionMatchMode {
+    fn default() -> Self {
+        CompletionMatchMode::ExactPrefix
+    }
+}
+
+/// Find the nearest ancestor of `cwd` that is a git working directory, by
+/// walking upward for a `.git` marker via repository discovery. Returns
+/// `None` outside a repository, or for a bare repository with no working
+/// directory.
+fn find_project_root(cwd: &Path) -> Option<PathBuf> {
+    Repository::discover(cwd)
+        .ok()?
+        .workdir()
+        .map(Path::to_path_buf)
+}
+
 /// Get the tab completion values.
 ///
+/// `cwd` is the directory relative completions are resolved against; see
+/// [Session::cwd]. `display` controls how the resulting paths are rendered;
+/// see [CompletionDisplay].
+///
 /// todo: ignore non-unicode strings
-fn get_tab_completions(prefix: &str, is_command: bool) -> Vec<String> {
-    let prefix_path = Path::new(prefix);
+fn get_tab_completions(
+    prefix: &str,
+    is_command: bool,
+    matchers: &Matchers,
+    cwd: &Path,
+    display: CompletionDisplay,
+    match_mode: CompletionMatchMode,
+) -> Vec<String> {
+    let expanded = expand_prefix(prefix);
+    let search_str = expanded.as_deref().unwrap_or(prefix);
+    let prefix_path = Path::new(search_str);
 
     let has_parent = if let Some(parent) = prefix_path.parent() {
         !parent.as_os_str().is_empty()
@@ -77,7 +280,24 @@ fn get_tab_completions(prefix: &str, is_command: bool) -> Vec<String> {
     };
     let has_cur_dir = Some(Component::CurDir) == prefix_path.components().next();
 
-    let in_dir = completion::search_prefix(prefix_path).unwrap();
+    let display_base = match display {
+        CompletionDisplay::LiteralPrefix | CompletionDisplay::Cwd => cwd.to_path_buf(),
+        CompletionDisplay::Root => find_project_root(cwd).unwrap_or_else(|| cwd.to_path_buf()),
+    };
+
+    let render = |path: &Path| -> String {
+        let formatted = format_completion(path);
+
+        if display == CompletionDisplay::LiteralPrefix {
+            restore_original_prefix(formatted, prefix, expanded.as_deref())
+        } else {
+            formatted
+        }
+    };
+
+    let search_path = expand_path_with(search_str, cwd);
+    let in_dir =
+        completion::search_prefix(search_path.as_path(), display_base.as_path(), matchers, match_mode).unwrap();
 
     if is_command {
         // if the prefix has a parent component, search for directories or executables
@@ -85,7 +305,7 @@ fn get_tab_completions(prefix: &str, is_command: bool) -> Vec<String> {
             return in_dir
                 .filter_map(|path| {
                     if path.executable() {
-                        Some(path.to_string_lossy().to_string())
+                        Some(render(path.as_path()))
                     } else {
                         None
                     }
@@ -95,11 +315,11 @@ fn get_tab_completions(prefix: &str, is_command: bool) -> Vec<String> {
 
         // if the prefix does not have a parent component, search on path or directories
         let path_var = env::var("PATH").unwrap_or_else(|_| "".to_string());
-        let in_path = completion::search_path(prefix_path, path_var.as_str())
+        let in_path = completion::search_path(prefix_path, cwd, path_var.as_str(), matchers, match_mode)
             .unwrap()
             .filter_map(|path| {
                 if !has_cur_dir {
-                    Some(path.to_string_lossy().to_string())
+                    Some(format_completion(path.as_path()))
                 } else {
                     None
                 }
@@ -108,7 +328,7 @@ fn get_tab_completions(prefix: &str, is_command: bool) -> Vec<String> {
         in_dir
             .filter_map(|path| {
                 if path.is_dir() || path.executable() && has_cur_dir {
-                    Some(path.to_string_lossy().to_string())
+                    Some(render(path.as_path()))
                 } else {
                     None
                 }
@@ -116,9 +336,7 @@ fn get_tab_completions(prefix: &str, is_command: bool) -> Vec<String> {
             .chain(in_path)
             .collect()
     } else {
-        in_dir
-            .map(|path| path.to_string_lossy().to_string())
-            .collect()
+        in_dir.map(|path| render(path.as_path())).collect()
     }
 }
 
@@ -206,6 +424,28 @@ pub struct Session<'shell> {
     pub base: &'shell str,
 
     mode: SessionMode,
+
+    jobs: Jobs,
+
+    git_prompt_format: String,
+
+    prompt_format: String,
+
+    aliases: BTreeMap<String, String>,
+
+    last_output: Vec<u8>,
+
+    completion_matchers: Matchers,
+
+    dynamic_completion: bool,
+
+    pwd: String,
+
+    passthrough_commands: BTreeSet<String>,
+
+    completion_display: CompletionDisplay,
+
+    completion_match_mode: CompletionMatchMode,
 }
 
 impl<'shell> Session<'shell> {
@@ -220,9 +460,79 @@ impl<'shell> Session<'shell> {
             is_frozen,
             base,
             mode,
+            jobs: Jobs::new(),
+            git_prompt_format: crate::git_status::DEFAULT_FORMAT.to_string(),
+            prompt_format: crate::DEFAULT_PROMPT_FORMAT.to_string(),
+            aliases: BTreeMap::new(),
+            last_output: Vec::new(),
+            completion_matchers: Matchers::default(),
+            dynamic_completion: false,
+            pwd: env::current_dir()
+                .map(|path| path.to_string_lossy().to_string())
+                .unwrap_or_else(|_| String::from("/")),
+            passthrough_commands: vec!["sudo", "doas"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            completion_display: CompletionDisplay::default(),
+            completion_match_mode: CompletionMatchMode::default(),
         }
     }
 
+    /// Register or replace an alias, expanded by [Session::expand_aliases]
+    /// whenever it is the leading word of a line.
+    pub fn set_alias(&mut self, name: String, body: String) {
+        self.aliases.insert(name, body);
+    }
+
+    /// Remove a previously registered alias, returning its body if it existed.
+    pub fn remove_alias(&mut self, name: &str) -> Option<String> {
+        self.aliases.remove(name)
+    }
+
+    /// Look up a previously registered alias by name.
+    pub fn get_alias(&self, name: &str) -> Option<&String> {
+        self.aliases.get(name)
+    }
+
+    /// Iterate over all registered aliases in name order, for the `alias`
+    /// builtin's bare-invocation listing.
+    pub fn aliases(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.aliases.iter()
+    }
+
+    /// Expand a leading alias on `line` against this session's alias table;
+    /// see [argv::alias::expand].
+    pub fn expand_aliases(&self, line: &str) -> Result<String, ArgumentError> {
+        argv::alias::expand(line, &self.aliases)
+    }
+
+    /// The format string used to render the git prompt segment; see
+    /// [crate::git_status::segment] for the supported placeholders.
+    pub fn git_prompt_format(&self) -> &str {
+        &self.git_prompt_format
+    }
+
+    pub fn set_git_prompt_format(&mut self, format: String) {
+        self.git_prompt_format = format;
+    }
+
+    /// Render the git status segment for the current directory, or an empty
+    /// string outside a repository.
+    pub fn git_prompt_segment(&self) -> String {
+        crate::git_status::segment(&self.cwd(), &self.git_prompt_format)
+    }
+
+    /// The format string used to render the overall prompt; see [crate::prompt]
+    /// for the supported placeholders.
+    pub fn prompt_format(&self) -> &str {
+        &self.prompt_format
+    }
+
+    pub fn set_prompt_format(&mut self, format: String) {
+        self.prompt_format = format;
+    }
+
     pub fn mode(&self) -> SessionMode {
         self.mode
     }
@@ -241,6 +551,43 @@ impl<'shell> Session<'shell> {
         self.base.to_string()
     }
 
+    /// The session's logical working directory; see [Session::cwd] for a
+    /// recovery-aware read of the process cwd and
+    /// [Session::change_directory] for how this is kept up to date.
+    pub fn pwd(&self) -> &str {
+        &self.pwd
+    }
+
+    /// Read the process's current directory, falling back to the last-known
+    /// logical [Session::pwd] if the real directory has since been removed
+    /// out from under the session.
+    pub fn cwd(&self) -> PathBuf {
+        env::current_dir().unwrap_or_else(|_| PathBuf::from(&self.pwd))
+    }
+
+    /// Change the working directory to `target`, resolved against the
+    /// logical [Session::pwd] rather than the process cwd. A trailing
+    /// separator on `target` is carried over onto the new logical pwd when
+    /// `target` has no `.`/`..` segments, so a `cd foo/` still reads as
+    /// `foo/` for the completion that follows it. `PWD` is kept in sync so
+    /// child processes see the same logical path.
+    pub fn change_directory(&mut self, target: &str) -> io::Result<()> {
+        let resolved = expand_path_with(target, Path::new(&self.pwd));
+
+        env::set_current_dir(&resolved)?;
+
+        let mut pwd = resolved.to_string_lossy().to_string();
+
+        if target.ends_with(std::path::MAIN_SEPARATOR) && !has_dot_segments(target) {
+            pwd.push(std::path::MAIN_SEPARATOR);
+        }
+
+        env::set_var("PWD", &pwd);
+        self.pwd = pwd;
+
+        Ok(())
+    }
+
     /// Take user input.
     ///
     /// todo: handle returning terminal mode to normal when session is in normal mode
@@ -269,191 +616,328 @@ impl<'shell> Session<'shell> {
         let mut history_offset: Option<usize> = None;
         let mut buffer_bak: Option<String> = None;
 
+        // Ctrl+R incremental history search: `Some(query)` while active, with
+        // `search_skip` counting how many matches for `query` to skip over
+        // (bumped on repeated Ctrl+R to walk to older matches).
+        let mut search_query: Option<String> = None;
+        let mut search_skip = 0usize;
+
         let mut was_tab_previous_key = false;
 
-        let prompt = prompt();
+        let prompt = prompt(self);
 
         write!(stdout, "{}", prompt)?;
         stdout.flush()?;
 
         // todo: implement some tab-completion (even if its just files)
         // todo: add support for ctrl+d && ctrl+c
-        for key in stdin.keys().filter_map(Result::ok) {
-            match key {
-                // character deletion
-                Key::Backspace => {
-                    if offset > 0 {
-                        buffer.remove(offset - 1);
-                        offset -= 1;
+        let mut keys = stdin.keys().filter_map(Result::ok);
+
+        while let Some(key) = keys.next() {
+            if search_query.is_none() && key == Key::Ctrl('r') {
+                buffer_bak = Some(buffer.clone());
+                search_query = Some(String::new());
+                search_skip = 0;
+            } else if let Some(query) = search_query.as_mut() {
+                match key {
+                    Key::Ctrl('r') => search_skip += 1,
+                    Key::Ctrl('g') | Key::Esc => {
+                        if let Some(bak) = buffer_bak.take() {
+                            buffer = bak;
+                        }
+                        offset = buffer.len();
+                        search_query = None;
+                        search_skip = 0;
                     }
-                }
-                Key::Delete => {
-                    if offset < buffer.len() {
-                        buffer.remove(offset);
+                    Key::Backspace => {
+                        query.pop();
+                        search_skip = 0;
                     }
-                }
-
-                // cursor movement
-                Key::Left => {
-                    if offset != 0 {
-                        offset -= 1;
+                    Key::Char('\n') => {
+                        buffer_bak = None;
+                        offset = buffer.len();
+                        search_query = None;
+                        search_skip = 0;
                     }
-                }
-                Key::Right => {
-                    if offset < buffer.len() {
-                        offset += 1;
+                    Key::Char(c) => {
+                        query.push(c);
+                        search_skip = 0;
                     }
+                    _ => { /* ignore other keys while searching */ }
                 }
-
-                Key::Up => {
-                    match history_offset {
-                        Some(n) => {
-                            if n + 1 < history_entries.len() {
-                                history_offset = Some(n + 1);
-                            }
+            } else {
+                match key {
+                    // character deletion
+                    Key::Backspace => {
+                        if offset > 0 {
+                            buffer.remove(offset - 1);
+                            offset -= 1;
                         }
-                        None => {
-                            history_offset = Some(0);
-                            buffer_bak = Some(buffer.clone());
+                    }
+                    Key::Delete => {
+                        if offset < buffer.len() {
+                            buffer.remove(offset);
                         }
-                    };
+                    }
 
-                    if let Some(entry) = history_entries.get(history_offset.unwrap()) {
-                        if entry.mode == SessionMode::Wrapped && !entry.is_builtin {
-                            buffer = entry.argv.clone();
-                        } else {
-                            buffer = entry.get_command();
+                    // cursor movement
+                    Key::Left => {
+                        if offset != 0 {
+                            offset -= 1;
                         }
-
-                        offset = buffer.len();
                     }
-                }
-                Key::Down => {
-                    if let Some(n) = history_offset {
-                        match n.cmp(&0usize) {
-                            Ordering::Greater => history_offset = Some(n - 1),
-                            Ordering::Equal => {
-                                history_offset = None;
-
-                                buffer = buffer_bak.unwrap();
-                                buffer_bak = None;
-                            }
-                            Ordering::Less => unreachable!(),
+                    Key::Right => {
+                        if offset < buffer.len() {
+                            offset += 1;
                         }
                     }
 
-                    if let Some(history_offset) = history_offset {
-                        if let Some(entry) = history_entries.get(history_offset) {
+                    Key::Up => {
+                        match history_offset {
+                            Some(n) => {
+                                if n + 1 < history_entries.len() {
+                                    history_offset = Some(n + 1);
+                                }
+                            }
+                            None => {
+                                history_offset = Some(0);
+                                buffer_bak = Some(buffer.clone());
+                            }
+                        };
+
+                        if let Some(entry) = history_entries.get(history_offset.unwrap()) {
                             if entry.mode == SessionMode::Wrapped && !entry.is_builtin {
                                 buffer = entry.argv.clone();
                             } else {
                                 buffer = entry.get_command();
                             }
+
+                            offset = buffer.len();
                         }
                     }
+                    Key::Down => {
+                        if let Some(n) = history_offset {
+                            match n.cmp(&0usize) {
+                                Ordering::Greater => history_offset = Some(n - 1),
+                                Ordering::Equal => {
+                                    history_offset = None;
+
+                                    buffer = buffer_bak.unwrap();
+                                    buffer_bak = None;
+                                }
+                                Ordering::Less => unreachable!(),
+                            }
+                        }
 
-                    offset = buffer.len();
-                }
+                        if let Some(history_offset) = history_offset {
+                            if let Some(entry) = history_entries.get(history_offset) {
+                                if entry.mode == SessionMode::Wrapped && !entry.is_builtin {
+                                    buffer = entry.argv.clone();
+                                } else {
+                                    buffer = entry.get_command();
+                                }
+                            }
+                        }
 
-                // content deletion
-                Key::Ctrl('u') => {
-                    buffer.replace_range(..offset, "");
-                    offset = 0;
-                }
-                Key::Ctrl('k') => buffer.replace_range(offset.., ""),
+                        offset = buffer.len();
+                    }
 
-                Key::Ctrl('w') => {
-                    let word_start = get_previous_boundary(buffer.as_str(), offset);
-                    buffer.replace_range(word_start..offset, "");
-                    offset = word_start;
-                }
+                    // content deletion
+                    Key::Ctrl('u') => {
+                        buffer.replace_range(..offset, "");
+                        offset = 0;
+                    }
+                    Key::Ctrl('k') => buffer.replace_range(offset.., ""),
 
-                // cursor control
-                Key::Ctrl('a') => offset = 0,
-                Key::Ctrl('e') => offset = buffer.len(),
+                    Key::Ctrl('w') => {
+                        let word_start = get_previous_boundary(buffer.as_str(), offset);
+                        buffer.replace_range(word_start..offset, "");
+                        offset = word_start;
+                    }
 
-                // todo: change to ctrl+left && ctrl+right
-                Key::Ctrl('b') => offset = get_previous_boundary(&buffer, offset),
-                Key::Ctrl('f') => offset = get_next_boundary(&buffer, offset),
+                    // cursor control
+                    Key::Ctrl('a') => offset = 0,
+                    Key::Ctrl('e') => offset = buffer.len(),
 
-                // screen control
-                // todo: write lines and scroll rather than clearing screen
-                Key::Ctrl('l') => {
-                    write!(stdout, "\r{}{}{}", All, Right(offset as u16), Goto(1, 1),)?
-                }
+                    // todo: change to ctrl+left && ctrl+right
+                    Key::Ctrl('b') => offset = get_previous_boundary(&buffer, offset),
+                    Key::Ctrl('f') => offset = get_next_boundary(&buffer, offset),
 
-                // exit shell
-                Key::Ctrl('d') => {
-                    buffer = "exit".to_string();
-                    break;
-                }
+                    // screen control
+                    // todo: write lines and scroll rather than clearing screen
+                    Key::Ctrl('l') => {
+                        write!(stdout, "\r{}{}{}", All, Right(offset as u16), Goto(1, 1),)?
+                    }
 
-                // tab completion
-                Key::Char('\t') => {
-                    let word_start = get_previous_boundary(buffer.as_str(), offset);
-                    let is_command = word_start == 0;
-                    let completions = get_tab_completions(&buffer[word_start..offset], is_command);
+                    // exit shell
+                    Key::Ctrl('d') => {
+                        buffer = "exit".to_string();
+                        break;
+                    }
 
-                    match completions.len().cmp(&1) {
-                        Ordering::Less => { /* do nothing */ }
-                        Ordering::Equal => {
-                            buffer.replace_range(word_start..offset, completions[0].as_str());
-                            offset = buffer.len();
-                        }
-                        Ordering::Greater => {
-                            if was_tab_previous_key {
-                                // handle previous tab hit
-                                let max_width =
-                                    completions.iter().fold(0, |acc, i| max(acc, i.len()));
-                                let entries_pre_line = get_entries_per_line(
-                                    2,
-                                    max_width,
-                                    termion::terminal_size().unwrap().0 as usize,
-                                );
-
-                                for (i, c) in completions.iter().enumerate() {
-                                    if i % entries_pre_line == 0 {
-                                        write!(stdout, "\n\r{:<width$}", c, width = max_width)?;
-                                    } else {
-                                        write!(stdout, "{:<width$}", c, width = max_width + 2)?;
+                    // edit the current line in $EDITOR (Ctrl+X Ctrl+E)
+                    Key::Ctrl('x') => {
+                        if let Some(Key::Ctrl('e')) = keys.next() {
+                            let editor = env::var("EDITOR")
+                                .or_else(|_| env::var("VISUAL"))
+                                .unwrap_or_else(|_| "vi".to_string());
+
+                            let file = NamedTempFile::new().and_then(|mut file| {
+                                file.write_all(buffer.as_bytes())?;
+                                file.flush()?;
+
+                                Ok(file)
+                            });
+
+                            match file {
+                                Ok(file) => {
+                                    stdout.suspend_raw_mode()?;
+                                    let status = Command::new(&editor).arg(file.path()).status();
+                                    stdout.activate_raw_mode()?;
+
+                                    match status {
+                                        Ok(status) if status.success() => {
+                                            if let Ok(edited) = fs::read_to_string(file.path()) {
+                                                buffer = edited.trim().to_string();
+                                                offset = buffer.len();
+                                            }
+                                        }
+                                        // non-zero exit: leave buffer unchanged
+                                        Ok(_) => { /* do nothing */ }
+                                        Err(_) => {
+                                            write!(stdout, "\r\n{} not found\r\n", editor)?;
+                                        }
                                     }
                                 }
-                            } else if let Some(common_prefix) =
-                                get_common_prefix(completions.as_slice())
-                            {
-                                buffer.replace_range(0..offset, common_prefix.as_str());
+                                Err(err) => {
+                                    write!(stdout, "\r\ncouldn't create temp file: {}\r\n", err)?;
+                                }
+                            }
+                        }
+                    }
+
+                    // tab completion
+                    Key::Char('\t') => {
+                        let word_start = get_previous_boundary(buffer.as_str(), offset);
+                        let is_command = word_start == 0
+                            || is_passthrough_target(
+                                buffer.as_str(),
+                                word_start,
+                                &self.passthrough_commands,
+                            );
+
+                        let dynamic_completions = if self.mode == SessionMode::Wrapped
+                            && self.dynamic_completion
+                            && !is_command
+                        {
+                            self.get_wrapped_completions(buffer.as_str(), word_start)
+                        } else {
+                            None
+                        };
+
+                        let completions = dynamic_completions.unwrap_or_else(|| {
+                            get_tab_completions(
+                                &buffer[word_start..offset],
+                                is_command,
+                                &self.completion_matchers,
+                                &self.cwd(),
+                                self.completion_display,
+                                self.completion_match_mode,
+                            )
+                        });
+
+                        match completions.len().cmp(&1) {
+                            Ordering::Less => { /* do nothing */ }
+                            Ordering::Equal => {
+                                let completion = completions[0].as_str();
+                                buffer.replace_range(word_start..offset, completion);
+
+                                // a directory keeps the cursor right after its
+                                // trailing separator so the next tab can descend
+                                // into it; anything else gets a trailing space
+                                // since it's a fully-resolved word
+                                if !completion.ends_with(std::path::MAIN_SEPARATOR) {
+                                    buffer.push(' ');
+                                }
+
                                 offset = buffer.len();
                             }
+                            Ordering::Greater => {
+                                if was_tab_previous_key {
+                                    // handle previous tab hit
+                                    let max_width =
+                                        completions.iter().fold(0, |acc, i| max(acc, i.len()));
+                                    let entries_pre_line = get_entries_per_line(
+                                        2,
+                                        max_width,
+                                        termion::terminal_size().unwrap().0 as usize,
+                                    );
+
+                                    for (i, c) in completions.iter().enumerate() {
+                                        if i % entries_pre_line == 0 {
+                                            write!(stdout, "\n\r{:<width$}", c, width = max_width)?;
+                                        } else {
+                                            write!(stdout, "{:<width$}", c, width = max_width + 2)?;
+                                        }
+                                    }
+                                } else if let Some(common_prefix) =
+                                    get_common_prefix(completions.as_slice())
+                                {
+                                    buffer.replace_range(0..offset, common_prefix.as_str());
+                                    offset = buffer.len();
+                                }
+                            }
                         }
                     }
-                }
 
-                Key::Char('\n') => {
-                    writeln!(stdout, "\r")?;
-                    break;
-                }
-                Key::Char(c) => {
-                    if offset == buffer.len() {
-                        buffer.push(c);
-                    } else {
-                        buffer.insert(offset, c);
+                    Key::Char('\n') => {
+                        writeln!(stdout, "\r")?;
+                        break;
+                    }
+                    Key::Char(c) => {
+                        if offset == buffer.len() {
+                            buffer.push(c);
+                        } else {
+                            buffer.insert(offset, c);
+                        }
+
+                        offset += 1;
                     }
 
-                    offset += 1;
-                }
+                    _ => { /* do nothing */ }
+                };
+            }
 
-                _ => { /* do nothing */ }
-            };
+            if let Some(query) = &search_query {
+                let matched = history_entries
+                    .iter()
+                    .filter(|entry| entry.get_command().contains(query.as_str()))
+                    .nth(search_skip)
+                    .map(|entry| entry.get_command());
 
-            // todo: replace final carriage return + Right(...) with Left(...)
-            write!(
-                stdout,
-                "\r{}{}{}\r{}",
-                AfterCursor,
-                prompt,
-                buffer,
-                Right((prompt.len() + offset) as u16),
-            )?;
+                if let Some(matched) = &matched {
+                    buffer = matched.clone();
+                    offset = buffer.len();
+                }
+
+                write!(
+                    stdout,
+                    "\r{}(reverse-i-search)`{}': {}",
+                    AfterCursor,
+                    query,
+                    matched.as_deref().unwrap_or(""),
+                )?;
+            } else {
+                // todo: replace final carriage return + Right(...) with Left(...)
+                write!(
+                    stdout,
+                    "\r{}{}{}\r{}",
+                    AfterCursor,
+                    prompt,
+                    buffer,
+                    Right((prompt.len() + offset) as u16),
+                )?;
+            }
 
             stdout.flush()?;
 
@@ -467,11 +951,32 @@ impl<'shell> Session<'shell> {
 
     /// Push the given command to the back of the in-memory history stack.
     ///
-    /// If the given command is a builtin, it will be added as having no bas
-    /// command and SessionMode::Normal.
-    pub fn push_to_history(&mut self, command: &str, is_builtin: bool) {
+    /// If the given command is a builtin, it will be added as having no base
+    /// command and the session's current mode. `exit_code` is the code the command
+    /// (or builtin) finished with and `duration_ms` is how long it took to
+    /// run, both stored alongside the entry.
+    pub fn push_to_history(
+        &mut self,
+        command: &str,
+        is_builtin: bool,
+        exit_code: i32,
+        duration_ms: u64,
+    ) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
         let entry = if is_builtin {
-            HistoryEntry::new(command.trim().to_string(), None, self.mode, true)
+            HistoryEntry::new(
+                command.trim().to_string(),
+                None,
+                self.mode,
+                true,
+                timestamp,
+                exit_code,
+                duration_ms,
+            )
         } else {
             match self.mode {
                 SessionMode::Wrapped => HistoryEntry::new(
@@ -479,10 +984,19 @@ impl<'shell> Session<'shell> {
                     Some(self.get_base()),
                     self.mode,
                     is_builtin,
+                    timestamp,
+                    exit_code,
+                    duration_ms,
+                ),
+                SessionMode::Normal => HistoryEntry::new(
+                    command.trim().to_string(),
+                    None,
+                    self.mode,
+                    false,
+                    timestamp,
+                    exit_code,
+                    duration_ms,
                 ),
-                SessionMode::Normal => {
-                    HistoryEntry::new(command.trim().to_string(), None, self.mode, false)
-                }
             }
         };
 
@@ -493,9 +1007,119 @@ impl<'shell> Session<'shell> {
         self.history.iter()
     }
 
-    pub fn history_sync(&self) -> Result<(), std::io::Error> {
+    pub fn jobs(&mut self) -> &mut Jobs {
+        &mut self.jobs
+    }
+
+    /// The number of background jobs still running, for a prompt indicator.
+    pub fn running_job_count(&self) -> usize {
+        self.jobs
+            .iter()
+            .filter(|(_, job)| job.state == crate::jobs::JobState::Running)
+            .count()
+    }
+
+    /// The interleaved stdout/stderr bytes captured from the most recently
+    /// run foreground command (see [crate::run]), exposed so a later config
+    /// option can tee them into a log file.
+    pub fn last_output(&self) -> &[u8] {
+        &self.last_output
+    }
+
+    pub(crate) fn set_last_output(&mut self, output: Vec<u8>) {
+        self.last_output = output;
+    }
+
+    /// The allow/deny pattern set used to filter tab completion results; see
+    /// [completion::Matchers].
+    pub fn set_completion_matchers(&mut self, matchers: Matchers) {
+        self.completion_matchers = matchers;
+    }
+
+    /// Whether to ask `base` to complete its own arguments via clap's
+    /// dynamic completion protocol instead of falling back to filesystem
+    /// completion; see [Session::get_wrapped_completions].
+    pub fn set_dynamic_completion_enabled(&mut self, enabled: bool) {
+        self.dynamic_completion = enabled;
+    }
+
+    /// Register `command` (e.g. `env`, `nice`, `time`) as a passthrough
+    /// wrapper: tab completion for its first argument completes a command
+    /// from `PATH` rather than a filename, just as for `sudo`/`doas`; see
+    /// [is_passthrough_target].
+    pub fn add_passthrough_command(&mut self, command: String) {
+        self.passthrough_commands.insert(command);
+    }
+
+    /// How tab completion entries are rendered; see [CompletionDisplay].
+    pub fn set_completion_display(&mut self, display: CompletionDisplay) {
+        self.completion_display = display;
+    }
+
+    /// Set how typed characters are matched against candidate completions;
+    /// see [CompletionMatchMode].
+    pub fn set_completion_match_mode(&mut self, mode: CompletionMatchMode) {
+        self.completion_match_mode = mode;
+    }
+
+    /// Ask `base` to complete the word under the cursor using clap's
+    /// dynamic completion convention: `<base> complete --index <i> --ifs
+    /// '\n' -- <base> <word0> <word1> ...`, where `i` is the zero-based
+    /// index of the word under the cursor and the trailing words are
+    /// `buffer` split into shell words. Each non-empty line of stdout is
+    /// treated as a candidate.
+    ///
+    /// Returns `None` if `base` doesn't exist, can't be spawned, or exits
+    /// non-zero, so the caller can fall back to filesystem completion.
+    fn get_wrapped_completions(&self, buffer: &str, word_start: usize) -> Option<Vec<String>> {
+        let words = argv::split::split_words(buffer).ok()?;
+        let index = buffer[..word_start].split_whitespace().count() + 1;
+
+        let output = Command::new(self.base)
+            .arg("complete")
+            .arg("--index")
+            .arg(index.to_string())
+            .arg("--ifs")
+            .arg("\n")
+            .arg("--")
+            .arg(self.base)
+            .args(&words)
+            .output()
+            .ok()?;
+
+        if !output.status.success() {
+            return None;
+        }
+
+        Some(
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect(),
+        )
+    }
+
+    pub fn history_sync(&mut self) -> Result<(), std::io::Error> {
         self.history.sync()
     }
+
+    pub fn history_export(
+        &self,
+        path: &Path,
+        format: HistoryFormat,
+        dedup: bool,
+    ) -> Result<(), crate::error::WrashError> {
+        self.history.export(path, format, dedup)
+    }
+
+    pub fn history_import(
+        &mut self,
+        path: &Path,
+        format: HistoryFormat,
+    ) -> Result<(), crate::error::WrashError> {
+        self.history.import(path, format)
+    }
 }
 
 impl Drop for Session<'_> {
@@ -620,7 +1244,9 @@ mod tests {
 
     /// these methods changes the cwd, only run with `--test-threads 1`
     mod test_get_tab_completion {
+        use crate::completion::Matchers;
         use crate::session;
+        use crate::session::{CompletionDisplay, CompletionMatchMode};
         use std::env;
         use std::path::{Path, PathBuf};
 
@@ -642,12 +1268,11 @@ mod tests {
             env::set_var("PATH", new_path);
 
             env::set_current_dir(new_cwd.as_path())?;
-            let actual = session::get_tab_completions("", true);
+            let actual = session::get_tab_completions("", true, &Matchers::default(), new_cwd.as_path(), CompletionDisplay::LiteralPrefix, CompletionMatchMode::ExactPrefix);
             env::set_current_dir(old_cwd)?;
 
             let expected: Vec<String> = vec![
-                // todo: add trailing slash for directory name (ie "directory/")
-                String::from("directory"),
+                String::from("directory/"),
                 // form path
                 String::from("a_final_file"),
                 String::from("yet_another_file"),
@@ -669,7 +1294,7 @@ mod tests {
             env::set_var("PATH", new_path);
 
             env::set_current_dir(new_cwd.as_path())?;
-            let actual = session::get_tab_completions("a", true);
+            let actual = session::get_tab_completions("a", true, &Matchers::default(), new_cwd.as_path(), CompletionDisplay::LiteralPrefix, CompletionMatchMode::ExactPrefix);
             env::set_current_dir(old_cwd)?;
 
             let expected: Vec<String> = vec![
@@ -693,12 +1318,12 @@ mod tests {
             env::set_var("PATH", new_path);
 
             env::set_current_dir(new_cwd.as_path())?;
-            let actual = session::get_tab_completions("./", true);
+            let actual = session::get_tab_completions("./", true, &Matchers::default(), new_cwd.as_path(), CompletionDisplay::LiteralPrefix, CompletionMatchMode::ExactPrefix);
             env::set_current_dir(old_cwd)?;
 
             let expected: Vec<String> = vec![
                 String::from("./a_file"),
-                String::from("./directory"),
+                String::from("./directory/"),
                 String::from("./some_other_file"),
             ];
 
@@ -718,7 +1343,7 @@ mod tests {
             env::set_var("PATH", new_path);
 
             env::set_current_dir(new_cwd.as_path())?;
-            let actual = session::get_tab_completions("../a", true);
+            let actual = session::get_tab_completions("../a", true, &Matchers::default(), new_cwd.as_path(), CompletionDisplay::LiteralPrefix, CompletionMatchMode::ExactPrefix);
             env::set_current_dir(old_cwd.as_path())?;
 
             let expected: Vec<String> =
@@ -741,13 +1366,13 @@ mod tests {
             env::set_var("PATH", new_path);
 
             env::set_current_dir(new_cwd.as_path())?;
-            let actual = session::get_tab_completions("", false);
+            let actual = session::get_tab_completions("", false, &Matchers::default(), new_cwd.as_path(), CompletionDisplay::LiteralPrefix, CompletionMatchMode::ExactPrefix);
             env::set_current_dir(old_cwd)?;
 
             let expected: Vec<String> = vec![
                 String::from("a_file"),
                 String::from("another_file"),
-                String::from("directory"),
+                String::from("directory/"),
                 String::from("some_other_file"),
             ];
 
@@ -767,7 +1392,7 @@ mod tests {
             env::set_var("PATH", new_path);
 
             env::set_current_dir(new_cwd.as_path())?;
-            let actual = session::get_tab_completions("a", false);
+            let actual = session::get_tab_completions("a", false, &Matchers::default(), new_cwd.as_path(), CompletionDisplay::LiteralPrefix, CompletionMatchMode::ExactPrefix);
             env::set_current_dir(old_cwd)?;
 
             let expected: Vec<String> = vec![String::from("a_file"), String::from("another_file")];
@@ -788,7 +1413,7 @@ mod tests {
             env::set_var("PATH", new_path);
 
             env::set_current_dir(new_cwd.as_path())?;
-            let actual = session::get_tab_completions("directory/", false);
+            let actual = session::get_tab_completions("directory/", false, &Matchers::default(), new_cwd.as_path(), CompletionDisplay::LiteralPrefix, CompletionMatchMode::ExactPrefix);
             env::set_current_dir(old_cwd)?;
 
             let expected: Vec<String> = vec![Path::new("directory")
@@ -813,7 +1438,7 @@ mod tests {
             env::set_var("PATH", new_path);
 
             env::set_current_dir(new_cwd.as_path())?;
-            let actual = session::get_tab_completions("./", false);
+            let actual = session::get_tab_completions("./", false, &Matchers::default(), new_cwd.as_path(), CompletionDisplay::LiteralPrefix, CompletionMatchMode::ExactPrefix);
             env::set_current_dir(old_cwd)?;
 
             let expected: Vec<String> = vec![
@@ -822,11 +1447,85 @@ mod tests {
                     .join("another_file")
                     .to_string_lossy()
                     .to_string(),
+                format!(
+                    "{}{}",
+                    Path::new(".").join("directory").to_string_lossy(),
+                    std::path::MAIN_SEPARATOR
+                ),
                 Path::new(".")
-                    .join("directory")
+                    .join("some_other_file")
                     .to_string_lossy()
                     .to_string(),
-                Path::new(".")
+            ];
+
+            assert_eq!(expected, actual);
+
+            Ok(())
+        }
+
+        #[ignore]
+        #[test]
+        fn test_get_tab_completion_cwd_display() -> Result<(), Box<dyn std::error::Error>> {
+            let old_cwd = env::current_dir()?;
+            let new_cwd = get_resource_path(&["a_directory"]).canonicalize()?;
+
+            let new_path = get_resource_path(&["some_other_directory"]).canonicalize()?;
+
+            env::set_var("PATH", new_path);
+
+            env::set_current_dir(new_cwd.as_path())?;
+            let actual = session::get_tab_completions(
+                "./",
+                true,
+                &Matchers::default(),
+                new_cwd.as_path(),
+                CompletionDisplay::Cwd,
+                CompletionMatchMode::ExactPrefix,
+            );
+            env::set_current_dir(old_cwd)?;
+
+            let expected: Vec<String> = vec![
+                String::from("a_file"),
+                format!("directory{}", std::path::MAIN_SEPARATOR),
+                String::from("some_other_file"),
+            ];
+
+            assert_eq!(expected, actual);
+
+            Ok(())
+        }
+
+        #[ignore]
+        #[test]
+        fn test_get_tab_completion_root_display() -> Result<(), Box<dyn std::error::Error>> {
+            let old_cwd = env::current_dir()?;
+            let new_cwd = get_resource_path(&["a_directory"]).canonicalize()?;
+
+            let new_path = get_resource_path(&["some_other_directory"]).canonicalize()?;
+
+            env::set_var("PATH", new_path);
+
+            env::set_current_dir(new_cwd.as_path())?;
+            let actual = session::get_tab_completions(
+                "./",
+                true,
+                &Matchers::default(),
+                new_cwd.as_path(),
+                CompletionDisplay::Root,
+                CompletionMatchMode::ExactPrefix,
+            );
+            env::set_current_dir(old_cwd)?;
+
+            let resource_dir = get_resource_path(&["a_directory"]);
+
+            let expected: Vec<String> = vec![
+                resource_dir.join("a_file").to_string_lossy().to_string(),
+                format!(
+                    "{}{}",
+                    resource_dir.join("directory").to_string_lossy(),
+                    std::path::MAIN_SEPARATOR
+                ),
+                resource_dir
                     .join("some_other_file")
                     .to_string_lossy()
                     .to_string(),
@@ -838,6 +1537,145 @@ mod tests {
         }
     }
 
+    mod test_expand_ndots {
+        use crate::session;
+
+        #[test]
+        fn test_plain_dot_is_unchanged() {
+            assert_eq!(None, session::expand_ndots("."));
+        }
+
+        #[test]
+        fn test_plain_dot_dot_is_unchanged() {
+            assert_eq!(None, session::expand_ndots(".."));
+        }
+
+        #[test]
+        fn test_three_dots() {
+            assert_eq!(Some("../..".to_string()), session::expand_ndots("..."));
+        }
+
+        #[test]
+        fn test_four_dots() {
+            assert_eq!(Some("../../..".to_string()), session::expand_ndots("...."));
+        }
+
+        #[test]
+        fn test_embedded_in_longer_path() {
+            assert_eq!(
+                Some("../../foo".to_string()),
+                session::expand_ndots(".../foo")
+            );
+            assert_eq!(
+                Some("foo/../../bar".to_string()),
+                session::expand_ndots("foo/.../bar")
+            );
+        }
+
+        #[test]
+        fn test_no_ndots_returns_none() {
+            assert_eq!(None, session::expand_ndots("foo/bar"));
+        }
+    }
+
+    mod test_expand_tilde {
+        use crate::session;
+
+        #[test]
+        fn test_no_leading_tilde_returns_none() {
+            assert_eq!(None, session::expand_tilde("foo/bar"));
+        }
+
+        #[test]
+        fn test_unknown_user_returns_none() {
+            assert_eq!(
+                None,
+                session::expand_tilde("~wrash_test_nonexistent_user/foo")
+            );
+        }
+    }
+
+    mod test_restore_original_prefix {
+        use crate::session;
+
+        #[test]
+        fn test_restores_expanded_prefix() {
+            assert_eq!(
+                "~/Documents",
+                session::restore_original_prefix(
+                    "/home/alice/Documents".to_string(),
+                    "~/Doc",
+                    Some("/home/alice/Doc"),
+                )
+            );
+        }
+
+        #[test]
+        fn test_unexpanded_candidate_is_unchanged() {
+            assert_eq!(
+                "foo/bar",
+                session::restore_original_prefix("foo/bar".to_string(), "foo/bar", None)
+            );
+        }
+    }
+
+    mod test_is_passthrough_target {
+        use crate::session;
+        use std::collections::BTreeSet;
+
+        fn passthrough() -> BTreeSet<String> {
+            vec!["sudo", "doas"].into_iter().map(String::from).collect()
+        }
+
+        #[test]
+        fn test_first_word_after_passthrough_is_target() {
+            let buffer = "sudo ap";
+            let word_start = 5;
+
+            assert!(session::is_passthrough_target(
+                buffer,
+                word_start,
+                &passthrough()
+            ));
+        }
+
+        #[test]
+        fn test_passthrough_itself_is_not_a_target() {
+            let buffer = "sud";
+            let word_start = 0;
+
+            assert!(!session::is_passthrough_target(
+                buffer,
+                word_start,
+                &passthrough()
+            ));
+        }
+
+        #[test]
+        fn test_third_word_is_not_a_target() {
+            let buffer = "sudo apt ins";
+            let word_start = 9;
+
+            assert!(!session::is_passthrough_target(
+                buffer,
+                word_start,
+                &passthrough()
+            ));
+        }
+
+        #[test]
+        fn test_non_passthrough_first_word_is_not_a_target() {
+            let buffer = "git ch";
+            let word_start = 4;
+
+            assert!(!session::is_passthrough_target(
+                buffer,
+                word_start,
+                &passthrough()
+            ));
+        }
+    }
+
     mod test_common_prefix {
         use crate::session;
 
@@ -0,0 +1,231 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvError};
+use std::time::Duration;
+
+use glob::Pattern;
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use termion::clear;
+
+use crate::session::Session;
+use crate::WrashError;
+
+/// How long to wait for more filesystem events before triggering a re-run.
+/// A burst of saves (e.g. a build tool writing several files) collapses into
+/// a single run instead of one run per file.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Directories that are never watched, regardless of ignore files.
+const ALWAYS_IGNORED: &[&str] = &["target", ".git"];
+
+/// Read `.gitignore` and `.ignore` out of `dir`, if present, and compile
+/// their patterns into a glob set.
+fn load_ignore_patterns(dir: &Path) -> Vec<Pattern> {
+    let mut patterns = vec![];
+
+    for name in &[".gitignore", ".ignore"] {
+        let contents = match fs::read_to_string(dir.join(name)) {
+            Ok(contents) => contents,
+            Err(_) => continue,
+        };
+
+        for line in contents.lines() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Ok(pattern) = Pattern::new(line) {
+                patterns.push(pattern);
+            }
+        }
+    }
+
+    patterns
+}
+
+/// Check whether `path` should be skipped, either because it falls under an
+/// always-ignored directory or because it matches one of `patterns`.
+fn is_ignored(path: &Path, patterns: &[Pattern]) -> bool {
+    if path
+        .components()
+        .any(|c| ALWAYS_IGNORED.contains(&c.as_os_str().to_string_lossy().as_ref()))
+    {
+        return true;
+    }
+
+    patterns.iter().any(|pattern| pattern.matches_path(path))
+}
+
+/// Whether any of `events` - an entire drained burst from the debouncer -
+/// touches a path that isn't ignored, and so should trigger a single re-run
+/// for the whole burst.
+fn any_relevant_change(events: Vec<DebouncedEvent>, patterns: &[Pattern]) -> bool {
+    events.into_iter().any(|event| {
+        let path = match event {
+            DebouncedEvent::NoticeWrite(p)
+            | DebouncedEvent::NoticeRemove(p)
+            | DebouncedEvent::Create(p)
+            | DebouncedEvent::Write(p)
+            | DebouncedEvent::Chmod(p)
+            | DebouncedEvent::Remove(p) => Some(p),
+            DebouncedEvent::Rename(_, p) => Some(p),
+            DebouncedEvent::Rescan | DebouncedEvent::Error(_, _) => None,
+        };
+
+        match path {
+            Some(path) => !is_ignored(path.as_path(), patterns),
+            None => false,
+        }
+    })
+}
+
+/// Watch `paths` (the current directory if empty) and re-run `command`
+/// through `session`'s dispatch path every time a non-ignored file changes,
+/// until the watcher channel closes (e.g. the user sends Ctrl-C).
+pub fn run(paths: &[PathBuf], command: &str, session: &mut Session) -> Result<(), WrashError> {
+    let watch_paths = if paths.is_empty() {
+        vec![PathBuf::from(".")]
+    } else {
+        paths.to_vec()
+    };
+
+    let ignore_patterns = load_ignore_patterns(Path::new("."));
+
+    let (tx, rx) = channel();
+    let mut watcher = watcher(tx, DEBOUNCE)
+        .map_err(|err| WrashError::Custom(format!("could not start filesystem watcher: {}", err)))?;
+
+    for path in &watch_paths {
+        watcher
+            .watch(path, RecursiveMode::Recursive)
+            .map_err(|err| WrashError::Custom(format!("could not watch '{}': {}", path.display(), err)))?;
+    }
+
+    println!("watching for changes, press Ctrl-C to stop");
+
+    let mut stdout = std::io::stdout();
+    let mut stderr = std::io::stderr();
+    let mut should_continue = true;
+
+    loop {
+        let first = match rx.recv() {
+            Ok(event) => event,
+            Err(RecvError) => break,
+        };
+
+        // A burst of N filesystem events (e.g. a build tool rewriting
+        // several files) arrives as N separate DebouncedEvents in quick
+        // succession; drain every one that's already queued so the burst
+        // collapses into a single re-run instead of one per event.
+        let mut events = vec![first];
+
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+
+        if !any_relevant_change(events, &ignore_patterns) {
+            continue;
+        }
+
+        print!("{}", clear::All);
+        println!("$ {}", command);
+
+        let _ = crate::dispatch(command, session, &mut stdout, &mut stderr, &mut should_continue);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{any_relevant_change, is_ignored, load_ignore_patterns};
+    use notify::DebouncedEvent;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_always_ignored_target() {
+        let patterns = vec![];
+
+        assert!(is_ignored(Path::new("target/debug/main"), &patterns));
+    }
+
+    #[test]
+    fn test_always_ignored_git() {
+        let patterns = vec![];
+
+        assert!(is_ignored(Path::new(".git/HEAD"), &patterns));
+    }
+
+    #[test]
+    fn test_not_ignored() {
+        let patterns = vec![];
+
+        assert!(!is_ignored(Path::new("src/main.rs"), &patterns));
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        let patterns = vec![glob::Pattern::new("*.log").unwrap()];
+
+        assert!(is_ignored(Path::new("debug.log"), &patterns));
+        assert!(!is_ignored(Path::new("debug.rs"), &patterns));
+    }
+
+    #[test]
+    fn test_load_ignore_patterns_no_file() {
+        let patterns = load_ignore_patterns(Path::new("/no/such/directory"));
+
+        assert!(patterns.is_empty());
+    }
+
+    #[test]
+    fn test_any_relevant_change_burst_of_writes() {
+        let patterns = vec![];
+        let events = vec![
+            DebouncedEvent::Write(PathBuf::from("a.rs")),
+            DebouncedEvent::Write(PathBuf::from("b.rs")),
+            DebouncedEvent::Write(PathBuf::from("c.rs")),
+        ];
+
+        assert!(any_relevant_change(events, &patterns));
+    }
+
+    #[test]
+    fn test_any_relevant_change_all_ignored() {
+        let patterns = vec![];
+        let events = vec![
+            DebouncedEvent::Write(PathBuf::from("target/debug/main")),
+            DebouncedEvent::Write(PathBuf::from(".git/HEAD")),
+        ];
+
+        assert!(!any_relevant_change(events, &patterns));
+    }
+
+    #[test]
+    fn test_any_relevant_change_mixed_ignored_and_relevant() {
+        let patterns = vec![];
+        let events = vec![
+            DebouncedEvent::Write(PathBuf::from("target/debug/main")),
+            DebouncedEvent::Write(PathBuf::from("src/main.rs")),
+        ];
+
+        assert!(any_relevant_change(events, &patterns));
+    }
+
+    #[test]
+    fn test_any_relevant_change_no_events() {
+        let patterns = vec![];
+
+        assert!(!any_relevant_change(vec![], &patterns));
+    }
+
+    #[test]
+    fn test_any_relevant_change_rescan_only() {
+        let patterns = vec![];
+        let events = vec![DebouncedEvent::Rescan];
+
+        assert!(!any_relevant_change(events, &patterns));
+    }
+}